@@ -1,6 +1,11 @@
 use crate::server::alert;
+use crate::server::filter::PrereleasePolicy;
+use crate::server::source::github::{AppConfig, TokenSource};
+use crate::server::source::json::JsonPaths;
+use crate::server::source::SourceType;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
 use std::fs;
 use std::path::PathBuf;
@@ -10,6 +15,22 @@ use std::path::PathBuf;
 pub struct ServerConfig {
     #[serde(rename = "githubAuthorizationHeader")]
     pub github_authorization_header: String,
+    //More tokens to share the requests to GitHub with, each given inline,
+    //as {"env": "NAME"} or as {"file": "path"} with one token per line
+    #[serde(rename = "githubTokens")]
+    pub github_tokens: Vec<TokenSource>,
+    //Authorization header values keyed by GitHub host, e.g. a GitHub
+    //Enterprise server. The githubAuthorizationHeader and githubTokens are
+    //only sent to github.com, other hosts without an entry get no header
+    #[serde(rename = "githubCredentials")]
+    pub github_credentials: HashMap<String, String>,
+    //Authenticate as a GitHub App installation instead of with a static
+    //token, on the host the app belongs to
+    #[serde(rename = "githubApp")]
+    pub github_app: Option<AppConfig>,
+    //Fetch the releases of GitHub repos in batches through the GraphQL API
+    #[serde(rename = "githubGraphql")]
+    pub github_graphql: bool,
     #[serde(rename = "dbPath")]
     pub db_path: PathBuf,
     //Convert the unit of period to seconds
@@ -25,7 +46,45 @@ pub struct ServerConfig {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Repo {
     pub name: String,
+    //The api url of the repo, or the base url of the instance for sources
+    //that take a project
+    #[serde(default)]
     pub url: String,
+    #[serde(rename = "type", default)]
+    pub source_type: SourceType,
+    //A semver range the release version must satisfy, e.g. ">=2.0, <3"
+    #[serde(default)]
+    pub semver: Option<String>,
+    //Only the tags matching it are watched. For a feed it is matched
+    //against the entry title, and the version is taken from its first
+    //group, or the whole match
+    #[serde(rename = "tagRegex", default)]
+    pub tag_regex: Option<String>,
+    #[serde(rename = "excludeRegex", default)]
+    pub exclude_regex: Option<String>,
+    #[serde(default)]
+    pub prerelease: PrereleasePolicy,
+    //The project path or numeric id for gitlab, owner/repo for github and
+    //gitea
+    #[serde(default)]
+    pub project: Option<String>,
+    //The GitHub Enterprise host of the repo, github.com when not given
+    #[serde(default)]
+    pub host: Option<String>,
+    //The package name for package registries, the image name for oci
+    #[serde(default)]
+    pub package: Option<String>,
+    //The access token, or user:password for oci registries
+    #[serde(default)]
+    pub token: Option<String>,
+    //Watch the digest of this moving image tag instead of the tag list
+    #[serde(rename = "digestTag", default)]
+    pub digest_tag: Option<String>,
+    //Extra request headers, for the json source
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(rename = "jsonPath", default)]
+    pub json_path: Option<JsonPaths>,
 }
 
 impl Default for ServerConfig {
@@ -34,6 +93,10 @@ impl Default for ServerConfig {
         working_dir.push("data");
         Self {
             github_authorization_header: String::from(""),
+            github_tokens: Vec::new(),
+            github_credentials: HashMap::new(),
+            github_app: None,
+            github_graphql: false,
             db_path: working_dir,
             period: 7200,
             retry_interval: 600,
@@ -1,3 +1,4 @@
+use microkv::errors::KVError;
 use microkv::MicroKV;
 use serde::{Deserialize, Serialize};
 
@@ -6,6 +7,31 @@ pub struct Release {
     pub url: String,
     pub name: String,
     pub detail: ReleaseDetail,
+    /// The semantic version parsed from the tag name, if it is one.
+    pub version: Option<String>,
+    /// The version this release supersedes. Only set on alert events.
+    pub previous_version: Option<String>,
+    /// What the release is told apart by when its tag name is not unique,
+    /// such as the guid of a feed entry.
+    pub key: Option<String>,
+}
+
+// The layout releases were stored in before dedup keys were kept.
+#[derive(Deserialize)]
+struct UnkeyedRelease {
+    url: String,
+    name: String,
+    detail: ReleaseDetail,
+    version: Option<String>,
+    previous_version: Option<String>,
+}
+
+// The layout releases were stored in before versions were tracked.
+#[derive(Deserialize)]
+struct LegacyRelease {
+    url: String,
+    name: String,
+    detail: ReleaseDetail,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
@@ -18,6 +44,14 @@ pub struct ReleaseDetail {
     pub html_url: String,
 }
 
+/// The cache validators of the last response processed for a repo.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub struct Validators {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
 pub enum KeyFlag {
     Exist,
     NotExist,
@@ -26,12 +60,51 @@ pub enum KeyFlag {
 
 impl Release {
     pub fn new(url: String, name: String, detail: ReleaseDetail) -> Release {
-        Release { url, name, detail }
+        Release {
+            url,
+            name,
+            detail,
+            version: None,
+            previous_version: None,
+            key: None,
+        }
     }
+
+    /// The key a release is remembered by once it has been seen.
+    pub fn id(&self) -> &str {
+        self.key.as_deref().unwrap_or(self.detail.tag_name.as_str())
+    }
+}
+
+/// Read the release stored under `key`, upgrading one written in an older
+/// layout.
+pub fn get_release(db: &MicroKV, key: &str) -> microkv::errors::Result<Release> {
+    db.get_unwrap::<Release>(key)
+        .or_else(|e: KVError| {
+            let unkeyed: UnkeyedRelease = db.get_unwrap(key).map_err(|_| e)?;
+            let mut release = Release::new(unkeyed.url, unkeyed.name, unkeyed.detail);
+            release.version = unkeyed.version;
+            release.previous_version = unkeyed.previous_version;
+            Ok(release)
+        })
+        .or_else(|e: KVError| {
+            let legacy: LegacyRelease = db.get_unwrap(key).map_err(|_| e)?;
+            Ok(Release::new(legacy.url, legacy.name, legacy.detail))
+        })
+}
+
+/// The key holding the ids of the releases already seen for a repo.
+pub fn seen_key(name: &str) -> String {
+    format!("{}#seen", name)
+}
+
+/// The key holding the cache validators of a repo.
+pub fn validators_key(name: &str) -> String {
+    format!("{}#validators", name)
 }
 
 pub fn key_in_db_status(db: MicroKV, key: &str) -> KeyFlag {
-    match db.exists(&key) {
+    match db.exists(key) {
         Err(_) => KeyFlag::FnFail,
         Ok(flag) => {
             if flag {
@@ -42,3 +115,5 @@ pub fn key_in_db_status(db: MicroKV, key: &str) -> KeyFlag {
         }
     }
 }
+
+pub const SEEN_CAPACITY: usize = 256;
@@ -0,0 +1,149 @@
+use super::{Alert, Notifier};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::Utc;
+use log::trace;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AlertProvider {
+    #[serde(rename = "webhook-url")]
+    pub webhook_url: String,
+    //The "SEC..." secret of a robot secured by signature, empty when it is
+    //secured by keywords or ip instead
+    pub secret: String,
+}
+
+#[async_trait]
+impl Notifier for AlertProvider {
+    fn kind(&self) -> &'static str {
+        "dingtalk"
+    }
+
+    fn is_configured(&self) -> bool {
+        !self.webhook_url.is_empty()
+    }
+
+    async fn send(&self, alert: &Alert) -> anyhow::Result<()> {
+        let body = AlertProvider::build_http_body(alert);
+        let resp = super::post_json(
+            self.signed_url(Utc::now().timestamp_millis())?.as_str(),
+            body,
+        )
+        .await?;
+        // a refused message still comes with 200
+        let result: DingTalkResult = resp.json().await?;
+        if result.errcode != 0 {
+            return Err(anyhow!(
+                "dingtalk refused the alert. errcode = {}, {}",
+                result.errcode,
+                result.errmsg
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl AlertProvider {
+    fn build_http_body(alert: &Alert) -> Bytes {
+        let release = &alert.release;
+        let mut msg = format!(
+            "### {}\n- name: **{}**\n- tag: **{}**\n- release_name: {}\n- published_at: {}\n- url: [{}]({})",
+            alert.headline(),
+            release.name,
+            release.detail.tag_name,
+            release.detail.release_name,
+            release.detail.published_at,
+            release.detail.html_url,
+            release.detail.html_url,
+        );
+        if let Some(previous) = &release.previous_version {
+            msg.push_str(&format!("\n- previous_version: {}", previous));
+        }
+        let ding_data = DingData {
+            msgtype: "markdown".to_string(),
+            markdown: DingMarkdown {
+                title: format!("{} {}", release.name, release.detail.tag_name),
+                text: msg,
+            },
+        };
+
+        let tmp = json!(ding_data).to_string();
+        trace!("dingtalk json content: {}", tmp);
+        Bytes::from(tmp)
+    }
+
+    // the robot checks a signature of the timestamp in milliseconds, passed
+    // in the query along with it
+    fn signed_url(&self, timestamp: i64) -> anyhow::Result<Url> {
+        let mut url = Url::parse(&self.webhook_url)?;
+        if !self.secret.is_empty() {
+            let timestamp = timestamp.to_string();
+            let sign = super::hmac_sha256(
+                self.secret.as_bytes(),
+                format!("{}\n{}", timestamp, self.secret).as_bytes(),
+            );
+            url.query_pairs_mut()
+                .append_pair("timestamp", &timestamp)
+                .append_pair("sign", &sign);
+        }
+        Ok(url)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct DingData {
+    markdown: DingMarkdown,
+    msgtype: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct DingMarkdown {
+    // shown in the notification preview
+    title: String,
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DingTalkResult {
+    errcode: i64,
+    #[serde(default)]
+    errmsg: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn url_is_signed_with_the_secret() {
+        let provider = AlertProvider {
+            webhook_url: "https://oapi.dingtalk.com/robot/send?access_token=abc".to_string(),
+            secret: "SEC0123456789abcdef".to_string(),
+        };
+        // the sign is what the Python sample of the DingTalk docs gives for
+        // this secret and timestamp, url encoded with quote_plus as it does
+        assert_eq!(
+            provider.signed_url(1700000000000).unwrap().as_str(),
+            "https://oapi.dingtalk.com/robot/send?access_token=abc&timestamp=1700000000000\
+             &sign=TSZbRFUuvaSQaRKUpF970OPCb2%2FLcQAP3wOvwZIzBZk%3D"
+        );
+    }
+
+    #[test]
+    fn url_is_left_alone_without_a_secret() {
+        let provider = AlertProvider {
+            webhook_url: "https://oapi.dingtalk.com/robot/send?access_token=abc".to_string(),
+            secret: String::new(),
+        };
+        assert_eq!(
+            provider.signed_url(1700000000000).unwrap().as_str(),
+            "https://oapi.dingtalk.com/robot/send?access_token=abc"
+        );
+    }
+}
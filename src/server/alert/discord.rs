@@ -0,0 +1,227 @@
+use super::{Alert, Notifier};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::DateTime;
+use log::{trace, warn};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::time::{self, Duration};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AlertProvider {
+    #[serde(rename = "webhook-url")]
+    pub webhook_url: String,
+}
+
+#[async_trait]
+impl Notifier for AlertProvider {
+    fn kind(&self) -> &'static str {
+        "discord"
+    }
+
+    fn is_configured(&self) -> bool {
+        !self.webhook_url.is_empty()
+    }
+
+    async fn send(&self, alert: &Alert) -> anyhow::Result<()> {
+        let body = AlertProvider::build_http_body(alert);
+        // discord answers 429 with the seconds to wait before trying again
+        for _ in 0..DISCORD_MAX_ATTEMPTS {
+            let resp = super::post(&self.webhook_url, body.clone()).await?;
+            let status = resp.status();
+            if status.is_success() {
+                return Ok(());
+            }
+            if status != StatusCode::TOO_MANY_REQUESTS {
+                return Err(anyhow!(
+                    "cannot send the latest release info to alert provider. code = {}",
+                    status.as_u16()
+                ));
+            }
+            let secs = resp
+                .json::<RateLimited>()
+                .await
+                .ok()
+                .and_then(|v| v.retry_after)
+                .unwrap_or(DISCORD_DEFAULT_WAIT)
+                .clamp(0.0, DISCORD_MAX_WAIT);
+            warn!(
+                "discord rate limited the alert of {}. Retry after {} seconds",
+                alert.release.name, secs
+            );
+            time::sleep(Duration::from_secs_f64(secs)).await;
+        }
+        Err(anyhow!(
+            "discord is still rate limiting after {} attempts",
+            DISCORD_MAX_ATTEMPTS
+        ))
+    }
+}
+
+impl AlertProvider {
+    fn build_http_body(alert: &Alert) -> Bytes {
+        let release = &alert.release;
+        let mut fields = vec![
+            EmbedField::new("tag", &release.detail.tag_name),
+            EmbedField::new("release_name", &release.detail.release_name),
+        ];
+        if let Some(previous) = &release.previous_version {
+            fields.push(EmbedField::new("previous_version", previous));
+        }
+        let color = if release.detail.prerelease {
+            DISCORD_COLOR_PRERELEASE
+        } else {
+            DISCORD_COLOR_STABLE
+        };
+        // discord rejects the whole message over a malformed timestamp
+        let timestamp = DateTime::parse_from_rfc3339(&release.detail.published_at)
+            .ok()
+            .map(|v| v.to_rfc3339());
+        let embed = Embed {
+            title: truncate(
+                &format!("{} {}", release.name, release.detail.tag_name),
+                DISCORD_TITLE_LIMIT,
+            ),
+            url: Some(release.detail.html_url.clone()).filter(|v| v.starts_with("http")),
+            timestamp,
+            color,
+            fields,
+        };
+        let notice = DiscordNotice {
+            content: alert.headline(),
+            embeds: vec![embed],
+        };
+
+        let tmp = json!(notice).to_string();
+        trace!("discord json content: {}", tmp);
+        Bytes::from(tmp)
+    }
+}
+
+fn truncate(text: &str, limit: usize) -> String {
+    // only text over the limit is cut, leaving room for the ellipsis
+    match text.char_indices().nth(limit) {
+        Some(_) => {
+            let (i, _) = text.char_indices().nth(limit - 1).unwrap();
+            format!("{}…", &text[..i])
+        }
+        None => text.to_string(),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct DiscordNotice {
+    content: String,
+    embeds: Vec<Embed>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Embed {
+    title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<String>,
+    color: u32,
+    fields: Vec<EmbedField>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct EmbedField {
+    name: String,
+    value: String,
+    inline: bool,
+}
+
+impl EmbedField {
+    fn new(name: &str, value: &str) -> EmbedField {
+        // a field must not be empty
+        let value = match value.trim() {
+            "" => "-".to_string(),
+            v => truncate(v, DISCORD_FIELD_LIMIT),
+        };
+        EmbedField {
+            name: name.to_string(),
+            value,
+            inline: true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RateLimited {
+    // seconds, with a fraction
+    retry_after: Option<f64>,
+}
+
+const DISCORD_COLOR_STABLE: u32 = 0x2ecc71;
+const DISCORD_COLOR_PRERELEASE: u32 = 0xf2c744;
+const DISCORD_TITLE_LIMIT: usize = 256;
+const DISCORD_FIELD_LIMIT: usize = 1024;
+const DISCORD_MAX_ATTEMPTS: u8 = 3;
+// waits in seconds, when none is given and the longest honoured
+const DISCORD_DEFAULT_WAIT: f64 = 1.0;
+const DISCORD_MAX_WAIT: f64 = 60.0;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::source::stub::{Reply, Stub};
+
+    fn provider(server: &Stub) -> AlertProvider {
+        AlertProvider {
+            webhook_url: format!("{}/webhook", server.url()),
+        }
+    }
+
+    #[tokio::test]
+    async fn rate_limited_alert_is_sent_again() {
+        let server = Stub::start();
+        server.replies(
+            "/webhook",
+            vec![
+                Reply {
+                    status: 429,
+                    ..Reply::json(r#"{"retry_after": 0.05, "global": false}"#)
+                },
+                Reply::status(204),
+            ],
+        );
+        provider(&server)
+            .send(&super::super::tests::alert())
+            .await
+            .unwrap();
+        assert_eq!(server.requests(), ["POST /webhook", "POST /webhook"]);
+    }
+
+    #[tokio::test]
+    async fn rate_limited_alert_is_given_up() {
+        let server = Stub::start();
+        server.route(
+            "/webhook",
+            Reply {
+                status: 429,
+                ..Reply::json(r#"{"retry_after": 0}"#)
+            },
+        );
+        let err = provider(&server)
+            .send(&super::super::tests::alert())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("after 3 attempts"), "{}", err);
+        assert_eq!(server.requests().len(), DISCORD_MAX_ATTEMPTS as usize);
+    }
+
+    #[test]
+    fn text_is_truncated_on_a_char_boundary() {
+        assert_eq!(truncate("abc", 3), "abc");
+        assert_eq!(truncate("abcd", 3), "ab…");
+        // multi-byte chars are counted, not bytes
+        assert_eq!(truncate("日本語テキスト", 4), "日本語…");
+        assert_eq!(truncate("日本語", 3), "日本語");
+        assert_eq!(truncate(&"é".repeat(300), 256).chars().count(), 256);
+    }
+}
@@ -0,0 +1,150 @@
+use super::{Alert, Notifier};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::Utc;
+use log::trace;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// A Feishu or Lark custom bot, which only differ in the host of their
+/// webhook url.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AlertProvider {
+    #[serde(rename = "webhook-url")]
+    pub webhook_url: String,
+    //The secret of a bot with signature verification, empty when it has none
+    pub secret: String,
+}
+
+#[async_trait]
+impl Notifier for AlertProvider {
+    fn kind(&self) -> &'static str {
+        "feishu"
+    }
+
+    fn is_configured(&self) -> bool {
+        !self.webhook_url.is_empty()
+    }
+
+    async fn send(&self, alert: &Alert) -> anyhow::Result<()> {
+        let body = self.build_http_body(alert);
+        let resp = super::post_json(&self.webhook_url, body).await?;
+        // a refused message still comes with 200
+        let result: FeishuResult = resp.json().await?;
+        if let Some(code) = result.code.or(result.status_code).filter(|v| *v != 0) {
+            return Err(anyhow!(
+                "feishu refused the alert. code = {}, {}",
+                code,
+                result.msg.or(result.status_message).unwrap_or_default()
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl AlertProvider {
+    fn build_http_body(&self, alert: &Alert) -> Bytes {
+        let release = &alert.release;
+        let mut msg = format!(
+            "**name:** {}\n**tag:** {}\n**release_name:** {}\n**published_at:** {}",
+            release.name,
+            release.detail.tag_name,
+            release.detail.release_name,
+            release.detail.published_at,
+        );
+        if let Some(previous) = &release.previous_version {
+            msg.push_str(&format!("\n**previous_version:** {}", previous));
+        }
+        let template = if release.detail.prerelease {
+            FEISHU_COLOR_PRERELEASE
+        } else {
+            FEISHU_COLOR_STABLE
+        };
+        let card = json!({
+            "header": {
+                "title": { "tag": "plain_text", "content": alert.headline() },
+                "template": template,
+            },
+            "elements": [
+                { "tag": "div", "text": { "tag": "lark_md", "content": msg } },
+                {
+                    "tag": "action",
+                    "actions": [{
+                        "tag": "button",
+                        "text": { "tag": "plain_text", "content": "Open release" },
+                        "url": release.detail.html_url,
+                        "type": "primary",
+                    }],
+                },
+            ],
+        });
+        let mut feishu_data = FeishuData {
+            msg_type: "interactive".to_string(),
+            card,
+            timestamp: None,
+            sign: None,
+        };
+        if !self.secret.is_empty() {
+            let timestamp = Utc::now().timestamp();
+            feishu_data.sign = Some(self.sign(timestamp));
+            feishu_data.timestamp = Some(timestamp.to_string());
+        }
+
+        let tmp = json!(feishu_data).to_string();
+        trace!("feishu json content: {}", tmp);
+        Bytes::from(tmp)
+    }
+
+    // the bot checks a signature of the timestamp in seconds, which is the
+    // key of the HMAC here, not the message
+    fn sign(&self, timestamp: i64) -> String {
+        let key = format!("{}\n{}", timestamp, self.secret);
+        super::hmac_sha256(key.as_bytes(), b"")
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct FeishuData {
+    msg_type: String,
+    card: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sign: Option<String>,
+}
+
+// newer bots answer with code and msg, older ones with StatusCode
+#[derive(Debug, Deserialize)]
+struct FeishuResult {
+    code: Option<i64>,
+    msg: Option<String>,
+    #[serde(rename = "StatusCode")]
+    status_code: Option<i64>,
+    #[serde(rename = "StatusMessage")]
+    status_message: Option<String>,
+}
+
+const FEISHU_COLOR_STABLE: &str = "green";
+const FEISHU_COLOR_PRERELEASE: &str = "orange";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamp_is_signed_with_the_secret() {
+        let provider = AlertProvider {
+            webhook_url: String::new(),
+            secret: "fs0123456789abcdef".to_string(),
+        };
+        // what the Python sample of the Feishu docs gives for this secret
+        // and timestamp
+        assert_eq!(
+            provider.sign(1700000000),
+            "o/grjUNKAsJyYtOUMKmKJED/697UiCS+1bNn8ymZAcs="
+        );
+    }
+}
@@ -1,74 +1,333 @@
+pub mod dingtalk;
+pub mod discord;
+pub mod feishu;
 pub mod slack;
+pub mod teams;
+pub mod telegram;
 pub mod wechat;
 use crate::db::Release;
+use crate::server::source::SourceType;
 use crate::shutdown::Shutdown;
-use log::{error, info};
-use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::Bytes;
+use hmac::{Hmac, Mac};
+use log::{error, info, warn};
+use reqwest::header::{self, HeaderMap};
+use reqwest::{Client, Response};
+use serde::{Deserialize, Deserializer, Serialize};
+use sha2::Sha256;
 use std::sync::Arc;
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::sync::Semaphore;
+use tokio::time::Duration;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 #[serde(default)]
 pub struct Config {
-    pub slack: slack::AlertProvider,
-    pub wechat: wechat::AlertProvider,
+    #[serde(deserialize_with = "one_or_many")]
+    pub dingtalk: Vec<dingtalk::AlertProvider>,
+    #[serde(deserialize_with = "one_or_many")]
+    pub discord: Vec<discord::AlertProvider>,
+    #[serde(deserialize_with = "one_or_many")]
+    pub feishu: Vec<feishu::AlertProvider>,
+    #[serde(deserialize_with = "one_or_many")]
+    pub slack: Vec<slack::AlertProvider>,
+    #[serde(deserialize_with = "one_or_many")]
+    pub teams: Vec<teams::AlertProvider>,
+    #[serde(deserialize_with = "one_or_many")]
+    pub telegram: Vec<telegram::AlertProvider>,
+    #[serde(deserialize_with = "one_or_many")]
+    pub wechat: Vec<wechat::AlertProvider>,
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Config {
-            slack: Default::default(),
-            wechat: Default::default(),
+/// A release to alert on, along with the kind of source it was found in.
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub release: Release,
+    pub source: SourceType,
+}
+
+impl Alert {
+    /// The headline of the alert, naming the kind of source the release is from.
+    pub fn headline(&self) -> String {
+        format!("New {} Release Version", self.source.label())
+    }
+}
+
+/// A channel that release alerts can be delivered to.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    /// The kind of the channel, used to label it in the logs.
+    fn kind(&self) -> &'static str;
+
+    /// Whether the channel has enough configuration to be used.
+    fn is_configured(&self) -> bool;
+
+    async fn send(&self, alert: &Alert) -> Result<()>;
+}
+
+/// All the configured channels an alert is dispatched to.
+#[derive(Clone, Default)]
+pub struct Registry {
+    notifiers: Vec<(String, Arc<dyn Notifier>)>,
+}
+
+impl Registry {
+    pub fn from_config(config: &Config) -> Registry {
+        let mut registry = Registry::default();
+        registry.register_all(&config.dingtalk);
+        registry.register_all(&config.discord);
+        registry.register_all(&config.feishu);
+        registry.register_all(&config.slack);
+        registry.register_all(&config.teams);
+        registry.register_all(&config.telegram);
+        registry.register_all(&config.wechat);
+        registry
+    }
+
+    fn register_all<T: Notifier + Clone + 'static>(&mut self, providers: &[T]) {
+        for (i, v) in providers.iter().enumerate() {
+            if !v.is_configured() {
+                continue;
+            }
+            let label = format!("{}#{}", v.kind(), i);
+            self.notifiers.push((label, Arc::new(v.clone())));
         }
     }
+
+    pub fn len(&self) -> usize {
+        self.notifiers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notifiers.is_empty()
+    }
+
+    /// Send the alert to every channel and report the outcome per channel.
+    pub async fn dispatch(&self, alert: &Alert) -> Vec<(String, Result<()>)> {
+        let tasks = self
+            .notifiers
+            .iter()
+            .map(|(label, notifier)| async move { (label.clone(), notifier.send(alert).await) });
+        futures::future::join_all(tasks).await
+    }
 }
 
 pub async fn do_alert(
     alert: Config,
     mut notify_shutdown_alert: Shutdown,
     _shutdown_complete_tx_alert: Sender<()>,
-    release_rx: Receiver<Release>,
+    release_rx: Receiver<Alert>,
 ) {
     info!("Start doing alert repo release.");
+    let registry = Registry::from_config(&alert);
+    if registry.is_empty() {
+        warn!("No alert channel is configured. New releases will only be logged.");
+    } else {
+        info!("{} alert channel(s) configured.", registry.len());
+    }
 
-    tokio::join!(notify_shutdown_alert.recv(), try_alert(release_rx, alert));
+    tokio::join!(
+        notify_shutdown_alert.recv(),
+        try_alert(release_rx, registry)
+    );
     info!("alert module is stopping.");
 }
 
-async fn try_alert(mut release_rx: Receiver<Release>, alert: Config) {
+async fn try_alert(mut release_rx: Receiver<Alert>, registry: Registry) {
     let semaphore = Arc::new(Semaphore::new(4));
+    let registry = Arc::new(registry);
     while let Some(v) = release_rx.recv().await {
         if semaphore.acquire().await.is_ok() {
-            let alert = alert.clone();
+            let registry = registry.clone();
             tokio::spawn(async move {
-                if !alert.slack.webhook_url.is_empty() {
-                    match alert.slack.send(v.clone()).await {
+                let reports = registry.dispatch(&v).await;
+                let total = reports.len();
+                let mut sent = 0;
+                for (channel, result) in reports.into_iter() {
+                    match result {
                         Ok(_) => {
-                            info!("repo:{} - send alert to slack!", v.name);
+                            sent += 1;
+                            info!("repo:{} - send alert to {}!", v.release.name, channel);
                         }
                         Err(e) => {
-                            error!("{}", e);
+                            error!(
+                                "repo:{} - send alert to {} failed. Error: {}",
+                                v.release.name, channel, e
+                            );
                         }
                     }
                 }
-                if !alert.wechat.webhook_url.is_empty() {
-                    match alert.wechat.send(v.clone()).await {
-                        Ok(_) => {
-                            info!("repo:{} - send alert to wechat!", v.name);
-                        }
-                        Err(e) => {
-                            error!("{}", e);
-                        }
-                    }
+                if sent < total {
+                    warn!(
+                        "repo:{} - alert delivered to {}/{} channels",
+                        v.release.name, sent, total
+                    );
                 }
             });
         } else {
             error!(
                 "the semaphore has been closed. Close task for send alert msg {}",
-                v.name
+                v.release.name
             );
         }
     }
     semaphore.close();
 }
+
+/// Post a json body to a webhook, failing on a non-success status code.
+pub(crate) async fn post_json(url: &str, body: Bytes) -> Result<Response> {
+    let resp = post(url, body).await?;
+
+    if !resp.status().is_success() {
+        return Err(anyhow!(
+            "cannot send the latest release info to alert provider. code = {}",
+            resp.status().as_u16()
+        ));
+    }
+
+    Ok(resp)
+}
+
+/// Post a json body to a webhook, leaving the status code to the caller.
+pub(crate) async fn post(url: &str, body: Bytes) -> Result<Response> {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static(HTTP_CONTENT_JSON),
+    );
+    let client = Client::builder()
+        .default_headers(headers)
+        .timeout(Duration::from_secs(5))
+        .build()?;
+
+    let resp = client.post(url).body(body).send().await?;
+
+    Ok(resp)
+}
+
+/// The base64 HMAC-SHA256 of `message`, which the chat robots of DingTalk
+/// and Feishu sign requests with.
+pub(crate) fn hmac_sha256(key: &[u8], message: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes a key of any length");
+    mac.update(message);
+    STANDARD.encode(mac.finalize().into_bytes())
+}
+
+/// Accept either a single provider object or a list of them, so that the
+/// older `"slack": {...}` form keeps working.
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        Many(Vec<T>),
+        One(T),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::Many(v) => v,
+        OneOrMany::One(v) => vec![v],
+    })
+}
+
+const HTTP_CONTENT_JSON: &str = "application/json";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::ReleaseDetail;
+    use crate::server::source::stub::{Reply, Stub};
+    use serde_json::json;
+
+    pub(crate) fn alert() -> Alert {
+        let detail = ReleaseDetail {
+            release_name: "v1.0.0".to_string(),
+            tag_name: "v1.0.0".to_string(),
+            prerelease: false,
+            published_at: "2024-01-01T00:00:00Z".to_string(),
+            html_url: "https://github.com/owner/repo/releases/tag/v1.0.0".to_string(),
+        };
+        Alert {
+            release: Release::new(String::new(), "repo".to_string(), detail),
+            source: SourceType::GithubTag,
+        }
+    }
+
+    #[test]
+    fn provider_is_given_as_one_or_many() {
+        let config: Config = serde_json::from_value(json!({
+            "slack": { "webhook-url": "https://hooks.slack.com/a" },
+            "wechat": [
+                { "webhook-url": "https://qyapi.weixin.qq.com/a" },
+                { "webhook-url": "https://qyapi.weixin.qq.com/b" },
+            ],
+        }))
+        .unwrap();
+        assert_eq!(config.slack.len(), 1);
+        assert_eq!(config.slack[0].webhook_url, "https://hooks.slack.com/a");
+        assert_eq!(config.wechat.len(), 2);
+        assert_eq!(
+            config.wechat[1].webhook_url,
+            "https://qyapi.weixin.qq.com/b"
+        );
+        assert!(config.discord.is_empty());
+    }
+
+    #[tokio::test]
+    async fn dispatch_reaches_every_channel() {
+        let server = Stub::start();
+        server.route("/slack", Reply::json("ok"));
+        server.route("/wechat", Reply::json(r#"{"errcode":0}"#));
+        let config: Config = serde_json::from_value(json!({
+            "slack": { "webhook-url": format!("{}/slack", server.url()) },
+            "wechat": [
+                { "webhook-url": format!("{}/wechat", server.url()) },
+                { "webhook-url": format!("{}/missing", server.url()) },
+                { "webhook-url": "" },
+            ],
+        }))
+        .unwrap();
+        let registry = Registry::from_config(&config);
+        // a provider without a webhook url is left out
+        assert_eq!(registry.len(), 3);
+
+        let mut reports = registry.dispatch(&alert()).await;
+        reports.sort_by(|a, b| a.0.cmp(&b.0));
+        let labels: Vec<&str> = reports.iter().map(|v| v.0.as_str()).collect();
+        assert_eq!(labels, ["slack#0", "wechat#0", "wechat#1"]);
+        assert!(reports[0].1.is_ok());
+        assert!(reports[1].1.is_ok());
+        assert!(reports[2].1.is_err());
+
+        let mut requests = server.requests();
+        requests.sort();
+        assert_eq!(requests, ["POST /missing", "POST /slack", "POST /wechat"]);
+        let body = &server.received()[0].body;
+        assert!(body.contains("New GitHub Release Version"), "{}", body);
+    }
+
+    #[test]
+    fn headline_names_the_source() {
+        let mut alert = alert();
+        assert_eq!(alert.headline(), "New GitHub Release Version");
+        alert.source = SourceType::Pypi;
+        assert_eq!(alert.headline(), "New PyPI Release Version");
+    }
+
+    #[test]
+    fn hmac_sha256_matches_rfc_4231() {
+        // test case 2 of RFC 4231, whose HMAC-SHA256 is given in hex as
+        // 5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843
+        assert_eq!(
+            hmac_sha256(b"Jefe", b"what do ya want for nothing?"),
+            "W9zBRr9gdU5qBCQmCJV1x1oAPwidJzmDnexYuWTsOEM="
+        );
+    }
+}
@@ -1,59 +1,39 @@
-use crate::db::Release;
-use anyhow::anyhow;
+use super::{Alert, Notifier};
+use async_trait::async_trait;
 use bytes::Bytes;
 use log::trace;
-use reqwest::header::{self, HeaderMap};
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tokio::time::Duration;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 #[serde(default)]
 pub struct AlertProvider {
     #[serde(rename = "webhook-url")]
     pub webhook_url: String,
 }
 
-impl Default for AlertProvider {
-    fn default() -> Self {
-        AlertProvider {
-            webhook_url: String::new(),
-        }
+#[async_trait]
+impl Notifier for AlertProvider {
+    fn kind(&self) -> &'static str {
+        "slack"
     }
-}
-
-impl AlertProvider {
-    pub async fn send(&self, release: Release) -> anyhow::Result<()> {
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            header::CONTENT_TYPE,
-            header::HeaderValue::from_static(SLACK_HTTP_CONTENT),
-        );
-        let client = Client::builder()
-            .default_headers(headers)
-            .timeout(Duration::from_secs(5))
-            .build()?;
-        let body = AlertProvider::build_http_body(release);
 
-        let resp = client
-            .post(self.webhook_url.clone())
-            .body(body)
-            .send()
-            .await?;
+    fn is_configured(&self) -> bool {
+        !self.webhook_url.is_empty()
+    }
 
-        if !resp.status().is_success() {
-            return Err(anyhow!(
-                "cannot send the latest release info to alert provider. code = {}",
-                resp.status().as_u16()
-            ));
-        }
+    async fn send(&self, alert: &Alert) -> anyhow::Result<()> {
+        let body = AlertProvider::build_http_body(alert);
+        super::post_json(&self.webhook_url, body).await?;
 
         Ok(())
     }
+}
 
-    fn build_http_body(release: Release) -> Bytes {
-        let msg = format!(
+impl AlertProvider {
+    fn build_http_body(alert: &Alert) -> Bytes {
+        let release = &alert.release;
+        let mut msg = format!(
             "*name:* {}\n*tag:* {}\n*release_name:* {}\n*publish_at:* {}\n*url:* {}\n",
             release.name,
             release.detail.tag_name,
@@ -61,6 +41,9 @@ impl AlertProvider {
             release.detail.published_at,
             release.detail.html_url,
         );
+        if let Some(previous) = &release.previous_version {
+            msg.push_str(&format!("*previous_version:* {}\n", previous));
+        }
         let block = SlackNoticeBlock {
             type_alias: "section".to_string(),
             text: SlackNoticeText {
@@ -72,7 +55,7 @@ impl AlertProvider {
             type_alias: "header".to_string(),
             text: SlackNoticeText {
                 type_alias: "plain_text".to_string(),
-                text: "New Github Release Version".to_string(),
+                text: alert.headline(),
             },
         };
         let attachment = SlackNoticeAttachment {
@@ -115,4 +98,3 @@ struct SlackNoticeText {
 }
 
 const SLACK_COLOR: &str = "#f2c744";
-const SLACK_HTTP_CONTENT: &str = "application/json";
@@ -0,0 +1,186 @@
+use super::{Alert, Notifier};
+use async_trait::async_trait;
+use bytes::Bytes;
+use log::trace;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Posts to a Teams incoming webhook or a Workflows "post to a channel when
+/// a webhook request is received" url, both of which take Adaptive Cards.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AlertProvider {
+    #[serde(rename = "webhook-url")]
+    pub webhook_url: String,
+}
+
+#[async_trait]
+impl Notifier for AlertProvider {
+    fn kind(&self) -> &'static str {
+        "teams"
+    }
+
+    fn is_configured(&self) -> bool {
+        !self.webhook_url.is_empty()
+    }
+
+    async fn send(&self, alert: &Alert) -> anyhow::Result<()> {
+        let body = AlertProvider::build_http_body(alert);
+        super::post_json(&self.webhook_url, body).await?;
+
+        Ok(())
+    }
+}
+
+impl AlertProvider {
+    fn build_http_body(alert: &Alert) -> Bytes {
+        let release = &alert.release;
+        let mut facts = vec![
+            Fact::new("name", &release.name),
+            Fact::new("tag", &release.detail.tag_name),
+            Fact::new("release_name", &release.detail.release_name),
+            Fact::new("published_at", &release.detail.published_at),
+        ];
+        if let Some(previous) = &release.previous_version {
+            facts.push(Fact::new("previous_version", previous));
+        }
+        let header = CardElement::TextBlock {
+            text: alert.headline(),
+            weight: "Bolder".to_string(),
+            size: "Medium".to_string(),
+            wrap: true,
+        };
+        let card = AdaptiveCard {
+            schema: ADAPTIVE_CARD_SCHEMA.to_string(),
+            type_alias: "AdaptiveCard".to_string(),
+            version: ADAPTIVE_CARD_VERSION.to_string(),
+            body: vec![header, CardElement::FactSet { facts }],
+            actions: vec![CardAction {
+                type_alias: "Action.OpenUrl".to_string(),
+                title: "Open release".to_string(),
+                url: release.detail.html_url.clone(),
+            }],
+        };
+        let teams_notice = TeamsNotice {
+            type_alias: "message".to_string(),
+            attachments: vec![TeamsAttachment {
+                content_type: ADAPTIVE_CARD_CONTENT_TYPE.to_string(),
+                content: card,
+            }],
+        };
+
+        let tmp = json!(teams_notice).to_string();
+        trace!("teams json content: {}", tmp);
+        Bytes::from(tmp)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct TeamsNotice {
+    #[serde(rename = "type")]
+    type_alias: String,
+    attachments: Vec<TeamsAttachment>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct TeamsAttachment {
+    #[serde(rename = "contentType")]
+    content_type: String,
+    content: AdaptiveCard,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct AdaptiveCard {
+    #[serde(rename = "$schema")]
+    schema: String,
+    #[serde(rename = "type")]
+    type_alias: String,
+    version: String,
+    body: Vec<CardElement>,
+    actions: Vec<CardAction>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type")]
+enum CardElement {
+    TextBlock {
+        text: String,
+        weight: String,
+        size: String,
+        wrap: bool,
+    },
+    FactSet {
+        facts: Vec<Fact>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Fact {
+    title: String,
+    value: String,
+}
+
+impl Fact {
+    fn new(title: &str, value: &str) -> Fact {
+        Fact {
+            title: title.to_string(),
+            value: value.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct CardAction {
+    #[serde(rename = "type")]
+    type_alias: String,
+    title: String,
+    url: String,
+}
+
+const ADAPTIVE_CARD_SCHEMA: &str = "http://adaptivecards.io/schemas/adaptive-card.json";
+const ADAPTIVE_CARD_CONTENT_TYPE: &str = "application/vnd.microsoft.card.adaptive";
+// the newest version Teams renders on every client
+const ADAPTIVE_CARD_VERSION: &str = "1.4";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::source::stub::{Reply, Stub};
+    use serde_json::Value;
+
+    #[tokio::test]
+    async fn adaptive_card_is_posted() {
+        let server = Stub::start();
+        server.route("/webhook", Reply::status(202));
+        let provider = AlertProvider {
+            webhook_url: format!("{}/webhook", server.url()),
+        };
+        let mut alert = super::super::tests::alert();
+        alert.release.previous_version = Some("v0.9.0".to_string());
+        provider.send(&alert).await.unwrap();
+
+        let received = server.received();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].header("Content-Type"), Some("application/json"));
+        let body: Value = serde_json::from_str(&received[0].body).unwrap();
+        assert_eq!(body["type"], "message");
+        let attachment = &body["attachments"][0];
+        assert_eq!(
+            attachment["contentType"],
+            "application/vnd.microsoft.card.adaptive"
+        );
+        let card = &attachment["content"];
+        assert_eq!(card["type"], "AdaptiveCard");
+        assert_eq!(card["version"], "1.4");
+        assert_eq!(card["body"][0]["type"], "TextBlock");
+        assert_eq!(card["body"][0]["text"], "New GitHub Release Version");
+        let facts = card["body"][1]["facts"].as_array().unwrap();
+        assert_eq!(facts.last().unwrap()["title"], "previous_version");
+        assert_eq!(facts.last().unwrap()["value"], "v0.9.0");
+        assert_eq!(card["actions"][0]["type"], "Action.OpenUrl");
+        assert_eq!(
+            card["actions"][0]["url"],
+            "https://github.com/owner/repo/releases/tag/v1.0.0"
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use super::{Alert, Notifier};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use bytes::Bytes;
+use log::trace;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct AlertProvider {
+    #[serde(rename = "bot-token")]
+    pub bot_token: String,
+    pub chats: Vec<Chat>,
+    //The Bot API server, only changed for a local Bot API server or a stub
+    #[serde(rename = "api-url")]
+    pub api_url: String,
+}
+
+/// A chat to send alerts to: its id or `@username`, or an object with the
+/// topic of a forum group to post in.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Chat {
+    Topic {
+        #[serde(rename = "chat-id")]
+        chat_id: ChatId,
+        #[serde(rename = "thread-id")]
+        thread_id: i64,
+    },
+    Chat(ChatId),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum ChatId {
+    Id(i64),
+    Username(String),
+}
+
+impl Default for AlertProvider {
+    fn default() -> Self {
+        Self {
+            bot_token: String::new(),
+            chats: Vec::new(),
+            api_url: TELEGRAM_API_URL.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for AlertProvider {
+    fn kind(&self) -> &'static str {
+        "telegram"
+    }
+
+    fn is_configured(&self) -> bool {
+        !self.bot_token.is_empty() && !self.chats.is_empty()
+    }
+
+    async fn send(&self, alert: &Alert) -> anyhow::Result<()> {
+        let url = format!(
+            "{}/bot{}/sendMessage",
+            self.api_url.trim_end_matches('/'),
+            self.bot_token
+        );
+        let mut failed = Vec::new();
+        for chat in self.chats.iter() {
+            let body = AlertProvider::build_http_body(alert, chat);
+            if let Err(e) = AlertProvider::send_message(&url, body).await {
+                failed.push(format!("{}: {}", chat, e));
+            }
+        }
+        if !failed.is_empty() {
+            return Err(anyhow!(
+                "cannot send the latest release info to telegram chats. {}",
+                failed.join("; ")
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl AlertProvider {
+    fn build_http_body(alert: &Alert, chat: &Chat) -> Bytes {
+        let release = &alert.release;
+        let mut msg = format!(
+            "<b>{}</b>\n<b>name:</b> {}\n<b>tag:</b> <code>{}</code>\n<b>release_name:</b> {}\n<b>published_at:</b> {}\n<b>url:</b> <a href=\"{}\">{}</a>",
+            escape(&alert.headline()),
+            escape(&release.name),
+            escape(&release.detail.tag_name),
+            escape(&release.detail.release_name),
+            escape(&release.detail.published_at),
+            escape(&release.detail.html_url),
+            escape(&release.detail.html_url),
+        );
+        if let Some(previous) = &release.previous_version {
+            msg.push_str(&format!(
+                "\n<b>previous_version:</b> <code>{}</code>",
+                escape(previous)
+            ));
+        }
+        let (chat_id, thread_id) = match chat {
+            Chat::Topic { chat_id, thread_id } => (chat_id, Some(thread_id)),
+            Chat::Chat(chat_id) => (chat_id, None),
+        };
+        let mut message = json!({
+            "chat_id": chat_id,
+            "text": msg,
+            "parse_mode": "HTML",
+            "disable_web_page_preview": true,
+        });
+        if let Some(v) = thread_id {
+            message["message_thread_id"] = json!(v);
+        }
+
+        let tmp = message.to_string();
+        trace!("telegram json content: {}", tmp);
+        Bytes::from(tmp)
+    }
+
+    // the Bot API reports why a message was refused in the body
+    async fn send_message(url: &str, body: Bytes) -> anyhow::Result<()> {
+        let resp = match super::post(url, body).await {
+            Ok(v) => v,
+            // the url holds the bot token, keep it out of the error
+            Err(e) => match e.downcast::<reqwest::Error>() {
+                Ok(e) => return Err(anyhow!(e.without_url())),
+                Err(e) => return Err(e),
+            },
+        };
+        let status = resp.status();
+        let result: TelegramResult = resp.json().await.unwrap_or_default();
+        if !status.is_success() || !result.ok {
+            return Err(anyhow!(
+                "code = {}, {}",
+                status.as_u16(),
+                result.description.unwrap_or_default()
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Chat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Chat::Topic { chat_id, thread_id } => write!(f, "{}/{}", chat_id, thread_id),
+            Chat::Chat(chat_id) => write!(f, "{}", chat_id),
+        }
+    }
+}
+
+impl std::fmt::Display for ChatId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChatId::Id(v) => write!(f, "{}", v),
+            ChatId::Username(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+// the characters telegram's HTML parse mode takes as markup
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct TelegramResult {
+    ok: bool,
+    description: Option<String>,
+}
+
+const TELEGRAM_API_URL: &str = "https://api.telegram.org";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::source::stub::{Reply, Stub};
+    use serde_json::Value;
+
+    #[tokio::test]
+    async fn message_is_sent_to_every_chat() {
+        let server = Stub::start();
+        server.route("/bot123:secret/sendMessage", Reply::json(r#"{"ok":true}"#));
+        let provider: AlertProvider = serde_json::from_value(json!({
+            "bot-token": "123:secret",
+            "chats": [-100200, { "chat-id": "@releases", "thread-id": 42 }],
+            "api-url": server.url(),
+        }))
+        .unwrap();
+        let mut alert = super::super::tests::alert();
+        alert.release.detail.release_name = "<b>Fish & Chips</b>".to_string();
+        provider.send(&alert).await.unwrap();
+
+        let received = server.received();
+        assert_eq!(received.len(), 2);
+        let bodies: Vec<Value> = received
+            .iter()
+            .map(|v| serde_json::from_str(&v.body).unwrap())
+            .collect();
+        assert_eq!(bodies[0]["chat_id"], -100200);
+        assert_eq!(bodies[0]["parse_mode"], "HTML");
+        assert!(bodies[0].get("message_thread_id").is_none());
+        assert_eq!(bodies[1]["chat_id"], "@releases");
+        assert_eq!(bodies[1]["message_thread_id"], 42);
+        let text = bodies[0]["text"].as_str().unwrap();
+        assert!(
+            text.contains("&lt;b&gt;Fish &amp; Chips&lt;/b&gt;"),
+            "{}",
+            text
+        );
+        assert!(text.starts_with("<b>New GitHub Release Version</b>"));
+    }
+
+    #[tokio::test]
+    async fn bot_token_is_kept_out_of_the_error() {
+        let server = Stub::start();
+        server.route(
+            "/bot123:secret/sendMessage",
+            Reply {
+                status: 400,
+                ..Reply::json(r#"{"ok":false,"description":"Bad Request: chat not found"}"#)
+            },
+        );
+        let mut provider = AlertProvider {
+            bot_token: "123:secret".to_string(),
+            chats: vec![Chat::Chat(ChatId::Id(1))],
+            api_url: server.url().to_string(),
+        };
+        let err = provider
+            .send(&super::super::tests::alert())
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("chat not found"), "{}", err);
+        assert!(!err.contains("secret"), "{}", err);
+
+        // nothing listens there, so reqwest fails with the url in its error
+        provider.api_url = "http://127.0.0.1:1".to_string();
+        let err = provider
+            .send(&super::super::tests::alert())
+            .await
+            .unwrap_err();
+        let err = format!("{:#}", err);
+        assert!(!err.contains("secret"), "{}", err);
+    }
+}
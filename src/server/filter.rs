@@ -0,0 +1,226 @@
+use crate::config::Repo;
+use crate::db::Release;
+use anyhow::{Context, Result};
+use regex::Regex;
+use semver::{Prerelease, Version, VersionReq};
+use serde::{Deserialize, Serialize};
+
+/// How releases marked as pre-release are treated.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PrereleasePolicy {
+    Ignore,
+    #[default]
+    Include,
+    Only,
+}
+
+/// The per-repo rules deciding which releases are worth watching.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    semver: Option<VersionReq>,
+    tag_regex: Option<Regex>,
+    exclude_regex: Option<Regex>,
+    prerelease: PrereleasePolicy,
+}
+
+impl Filter {
+    pub fn from_repo(repo: &Repo) -> Result<Filter> {
+        let semver = match &repo.semver {
+            Some(v) => Some(
+                VersionReq::parse(v).with_context(|| format!("invalid semver range \"{}\"", v))?,
+            ),
+            None => None,
+        };
+        let tag_regex = match &repo.tag_regex {
+            Some(v) => Some(Regex::new(v).with_context(|| format!("invalid tagRegex \"{}\"", v))?),
+            None => None,
+        };
+        let exclude_regex = match &repo.exclude_regex {
+            Some(v) => {
+                Some(Regex::new(v).with_context(|| format!("invalid excludeRegex \"{}\"", v))?)
+            }
+            None => None,
+        };
+
+        Ok(Filter {
+            semver,
+            tag_regex,
+            exclude_regex,
+            prerelease: repo.prerelease,
+        })
+    }
+
+    /// Whether the release passes every configured rule. Expects
+    /// `release.version` to be filled in already.
+    pub fn matches(&self, release: &Release) -> bool {
+        let tag = release.detail.tag_name.as_str();
+        if let Some(re) = &self.tag_regex {
+            if !re.is_match(tag) {
+                return false;
+            }
+        }
+        if let Some(re) = &self.exclude_regex {
+            if re.is_match(tag) {
+                return false;
+            }
+        }
+
+        let version = release
+            .version
+            .as_deref()
+            .and_then(|v| Version::parse(v).ok());
+        let prerelease =
+            release.detail.prerelease || version.as_ref().is_some_and(|v| !v.pre.is_empty());
+        match self.prerelease {
+            PrereleasePolicy::Ignore if prerelease => return false,
+            PrereleasePolicy::Only if !prerelease => return false,
+            _ => {}
+        }
+
+        if let Some(req) = &self.semver {
+            let Some(mut version) = version else {
+                return false;
+            };
+            // a range like ">=2.0, <3" is meant for the whole 2.x line, so
+            // judge pre-releases by the version they lead up to
+            version.pre = Prerelease::EMPTY;
+            if !req.matches(&version) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::ReleaseDetail;
+    use crate::server::version;
+    use serde_json::{json, Value};
+
+    fn from_rules(rules: Value) -> Result<Filter> {
+        let mut repo = json!({ "name": "repo", "url": "https://github.com/a/b" });
+        repo.as_object_mut()
+            .unwrap()
+            .extend(rules.as_object().unwrap().clone());
+        Filter::from_repo(&serde_json::from_value(repo).unwrap())
+    }
+
+    // a release as watch.rs hands it over, with its version filled in
+    fn release(tag: &str, prerelease: bool) -> Release {
+        let detail = ReleaseDetail {
+            release_name: tag.to_string(),
+            tag_name: tag.to_string(),
+            prerelease,
+            published_at: "2024-01-01T00:00:00Z".to_string(),
+            html_url: String::new(),
+        };
+        let mut release = Release::new(String::new(), "repo".to_string(), detail);
+        release.version = version::parse(tag).map(|v| v.to_string());
+        release
+    }
+
+    fn passed(filter: &Filter, tags: &[&str]) -> Vec<String> {
+        tags.iter()
+            .filter(|v| filter.matches(&release(v, false)))
+            .map(|v| v.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn no_rules_pass_everything() {
+        let filter = from_rules(json!({})).unwrap();
+        assert_eq!(
+            passed(&filter, &["v1.0.0", "v2.0.0-rc.1", "nightly"]),
+            ["v1.0.0", "v2.0.0-rc.1", "nightly"]
+        );
+    }
+
+    #[test]
+    fn tags_are_picked_by_regex() {
+        let tags = [
+            "server-v1.0.0",
+            "client-v1.1.0",
+            "server-v1.2.0-nightly",
+            "server-v1.2.0",
+        ];
+        let filter = from_rules(json!({ "tagRegex": "^server-" })).unwrap();
+        assert_eq!(
+            passed(&filter, &tags),
+            ["server-v1.0.0", "server-v1.2.0-nightly", "server-v1.2.0"]
+        );
+        let filter =
+            from_rules(json!({ "tagRegex": "^server-", "excludeRegex": "nightly$" })).unwrap();
+        assert_eq!(passed(&filter, &tags), ["server-v1.0.0", "server-v1.2.0"]);
+        let filter = from_rules(json!({ "excludeRegex": "^client-" })).unwrap();
+        assert_eq!(
+            passed(&filter, &tags),
+            ["server-v1.0.0", "server-v1.2.0-nightly", "server-v1.2.0"]
+        );
+    }
+
+    #[test]
+    fn invalid_rules_are_refused() {
+        for rules in [
+            json!({ "tagRegex": "(" }),
+            json!({ "excludeRegex": "[" }),
+            json!({ "semver": "~>1" }),
+        ] {
+            assert!(from_rules(rules).is_err());
+        }
+    }
+
+    #[test]
+    fn prereleases_follow_the_policy() {
+        // marked by the source, or by the pre-release part of the version
+        let releases = [
+            release("v1.0.0", false),
+            release("v1.1.0", true),
+            release("v1.2.0-beta.1", false),
+            release("nightly", false),
+            release("1.0.post1", false),
+        ];
+        let passed = |policy: &str| -> Vec<&str> {
+            let filter = from_rules(json!({ "prerelease": policy })).unwrap();
+            releases
+                .iter()
+                .filter(|v| filter.matches(v))
+                .map(|v| v.detail.tag_name.as_str())
+                .collect()
+        };
+        assert_eq!(
+            passed("include"),
+            ["v1.0.0", "v1.1.0", "v1.2.0-beta.1", "nightly", "1.0.post1"]
+        );
+        assert_eq!(passed("ignore"), ["v1.0.0", "nightly", "1.0.post1"]);
+        assert_eq!(passed("only"), ["v1.1.0", "v1.2.0-beta.1"]);
+    }
+
+    #[test]
+    fn semver_range_judges_prereleases_by_their_release() {
+        let tags = [
+            "v1.9.0",
+            "v2.0.0-rc.1",
+            "v2.0.0",
+            "v2.5.1",
+            "v3.0.0-alpha",
+            "v3.0.0",
+        ];
+        let filter = from_rules(json!({ "semver": ">=2.0, <3" })).unwrap();
+        assert_eq!(passed(&filter, &tags), ["v2.0.0-rc.1", "v2.0.0", "v2.5.1"]);
+
+        let filter = from_rules(json!({ "semver": ">=2.0, <3", "prerelease": "ignore" })).unwrap();
+        assert_eq!(passed(&filter, &tags), ["v2.0.0", "v2.5.1"]);
+    }
+
+    #[test]
+    fn semver_range_refuses_tags_without_a_version() {
+        let filter = from_rules(json!({ "semver": ">=1" })).unwrap();
+        assert_eq!(
+            passed(&filter, &["nightly", "latest", "v1.0.0"]),
+            ["v1.0.0"]
+        );
+    }
+}
@@ -1,4 +1,7 @@
 pub mod alert;
+pub mod filter;
+pub mod source;
+pub mod version;
 pub mod watch;
 use crate::config;
 use crate::shutdown::Shutdown;
@@ -6,7 +9,7 @@ use anyhow::{anyhow, Context, Result};
 use clap::Args;
 use log::info;
 use microkv::MicroKV;
-use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::header::{self, HeaderMap};
 use std::path::PathBuf;
 use tokio::sync::mpsc::{self, Sender};
 
@@ -60,16 +63,26 @@ pub async fn execute(
         .context("Failed to create MicroKV from a stored file or create MicroKV for this file")?
         .set_auto_commit(true);
 
-    let headers = build_header(server_config.github_authorization_header)?;
+    let headers = build_header();
+    let credentials = source::github::Credentials::new(
+        &server_config.github_authorization_header,
+        &server_config.github_tokens,
+        &server_config.github_credentials,
+        server_config.github_app.as_ref(),
+    )?;
+    let ctx = source::Context::new(db, headers, credentials, server_config.github_graphql)?;
+    let puller_list = watch::build_puller_list(
+        server_config.repo_list.clone(),
+        &ctx,
+        server_config.retry_interval,
+    )?;
     let (release_tx, release_rx) = mpsc::channel(32);
 
     let watch = tokio::spawn(async move {
         watch::do_watch(
-            headers,
+            puller_list,
+            ctx,
             server_config.period,
-            server_config.retry_interval,
-            server_config.repo_list.clone(),
-            db.clone(),
             notify_shutdown_watch,
             shutdown_complete_tx_watch,
             release_tx,
@@ -92,10 +105,8 @@ pub async fn execute(
     Ok(())
 }
 
-fn build_header(token: String) -> Result<HeaderMap> {
-    let header_value = token
-        .parse::<HeaderValue>()
-        .context("cannot parse the given token to header value")?;
+// the Authorization header depends on the host, see `github::Credentials`
+fn build_header() -> HeaderMap {
     let mut headers = HeaderMap::new();
     headers.insert(
         "X-GitHub-Api-Version",
@@ -105,7 +116,6 @@ fn build_header(token: String) -> Result<HeaderMap> {
         header::ACCEPT,
         header::HeaderValue::from_static("application/vnd.github+json"),
     );
-    headers.insert(header::AUTHORIZATION, header_value);
 
-    Ok(headers)
+    headers
 }
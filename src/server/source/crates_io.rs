@@ -0,0 +1,163 @@
+use crate::config::Repo;
+use crate::db::{Release, ReleaseDetail};
+use crate::server::version;
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use log::{debug, trace};
+use serde::Deserialize;
+
+/// Watches the versions of a Rust crate published to crates.io, or to a
+/// registry serving the same web API.
+pub struct Source {
+    name: String,
+    // the registry, e.g. https://crates.io
+    base: String,
+    package: String,
+    ctx: super::Context,
+}
+
+#[derive(Debug, Deserialize)]
+struct Versions {
+    versions: Vec<CrateVersion>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CrateVersion {
+    num: String,
+    created_at: String,
+    #[serde(default)]
+    yanked: bool,
+}
+
+impl Source {
+    pub fn new(repo: &Repo, ctx: super::Context) -> Result<Source> {
+        let package = repo
+            .package
+            .clone()
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| anyhow!("repo {} needs the name of a crate", repo.name))?;
+        Ok(Source {
+            name: repo.name.clone(),
+            base: super::base_url(repo, CRATES_IO_URL),
+            package,
+            ctx,
+        })
+    }
+
+    fn versions_url(&self) -> String {
+        format!(
+            "{}/api/v1/crates/{}/versions?sort=date&per_page={}",
+            self.base,
+            super::encode_component(&self.package),
+            super::REGISTRY_VERSION_LIMIT
+        )
+    }
+}
+
+#[async_trait]
+impl super::ReleaseSource for Source {
+    async fn latest(&self) -> Result<Release> {
+        let mut releases = self.releases(None).await?;
+        releases.sort_by_cached_key(|v| version::parse(&v.detail.tag_name));
+        releases
+            .pop()
+            .ok_or_else(|| anyhow!("crate {} has no published version", self.package))
+    }
+
+    async fn releases(&self, _since: Option<&str>) -> Result<Vec<Release>> {
+        let url = self.versions_url();
+        let request = self.ctx.client.get(&url);
+        let resp = self
+            .ctx
+            .conditional_send(&self.name, &url, request)
+            .await?
+            .error_for_status()?;
+        let body: Versions = resp
+            .json()
+            .await
+            .context("Deserialize http response failed!")?;
+        debug!("Requested the versions of the crate {}", self.package);
+
+        // sorted by date, the newest versions are all on the first page
+        let releases: Vec<Release> = body
+            .versions
+            .into_iter()
+            // a yanked version is withdrawn, nobody should upgrade to it
+            .filter(|v| !v.yanked)
+            .map(|v| {
+                let detail = ReleaseDetail {
+                    release_name: format!("{} {}", self.package, v.num),
+                    prerelease: version::parse(&v.num).is_some_and(|v| !v.pre.is_empty()),
+                    published_at: v.created_at,
+                    html_url: format!("{}/crates/{}/{}", self.base, self.package, v.num),
+                    tag_name: v.num,
+                };
+                Release::new(self.base.clone(), self.name.clone(), detail)
+            })
+            .take(super::REGISTRY_VERSION_LIMIT)
+            .collect();
+        trace!(
+            "Collected {} versions of the crate {} that are not yanked.",
+            releases.len(),
+            self.package
+        );
+        Ok(releases)
+    }
+}
+
+const CRATES_IO_URL: &str = "https://crates.io";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::source::stub::{self, Reply, Stub};
+    use crate::server::source::ReleaseSource;
+    use crate::server::source::REGISTRY_VERSION_LIMIT;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn newest_versions_that_are_not_yanked() {
+        let registry = Stub::start();
+        // newest first, with more than a page of them
+        let versions: Vec<serde_json::Value> = (0..REGISTRY_VERSION_LIMIT + 5)
+            .rev()
+            .map(|i| {
+                json!({
+                    "num": format!("0.{}.0", i),
+                    "created_at": format!("2024-01-01T00:00:{:02}Z", i),
+                    "yanked": i == REGISTRY_VERSION_LIMIT + 3,
+                })
+            })
+            .collect();
+        registry.route(
+            "/api/v1/crates/demo/versions",
+            Reply::json(&json!({ "versions": versions }).to_string()),
+        );
+        let repo: Repo = serde_json::from_value(json!({
+            "name": "demo",
+            "type": "crates.io",
+            "url": registry.url(),
+            "package": "demo",
+        }))
+        .unwrap();
+        let source = Source::new(&repo, stub::context()).unwrap();
+
+        let releases = source.releases(None).await.unwrap();
+        assert_eq!(releases.len(), REGISTRY_VERSION_LIMIT);
+        let tags: Vec<&str> = releases
+            .iter()
+            .map(|v| v.detail.tag_name.as_str())
+            .collect();
+        let yanked = format!("0.{}.0", REGISTRY_VERSION_LIMIT + 3);
+        assert!(!tags.contains(&yanked.as_str()));
+        assert_eq!(tags[0], format!("0.{}.0", REGISTRY_VERSION_LIMIT + 4));
+        assert_eq!(releases[0].detail.release_name, format!("demo {}", tags[0]));
+        assert_eq!(
+            registry.requests(),
+            [format!(
+                "GET /api/v1/crates/demo/versions?sort=date&per_page={}",
+                REGISTRY_VERSION_LIMIT
+            )]
+        );
+    }
+}
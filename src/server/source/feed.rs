@@ -0,0 +1,221 @@
+use crate::config::Repo;
+use crate::db::{Release, ReleaseDetail};
+use crate::server::version;
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::SecondsFormat;
+use log::{debug, trace};
+use regex::Regex;
+
+/// Watches an RSS 2.0 or Atom feed of release notes, for projects that
+/// publish nothing else. Each entry is a release, told apart by its
+/// id (Atom) or guid (RSS) rather than by its title.
+pub struct Source {
+    name: String,
+    url: String,
+    // picks the version out of an entry title, titles are free text
+    tag_regex: Option<Regex>,
+    ctx: super::Context,
+}
+
+impl Source {
+    pub fn new(repo: &Repo, ctx: super::Context) -> Result<Source> {
+        if repo.url.is_empty() {
+            return Err(anyhow!("repo {} needs the url of a feed", repo.name));
+        }
+        let tag_regex = match &repo.tag_regex {
+            Some(v) => Some(Regex::new(v).with_context(|| format!("invalid tagRegex \"{}\"", v))?),
+            None => None,
+        };
+        Ok(Source {
+            name: repo.name.clone(),
+            url: repo.url.clone(),
+            tag_regex,
+            ctx,
+        })
+    }
+
+    /// The version in an entry title, only looked for with a tagRegex.
+    fn version(&self, title: &str) -> Option<semver::Version> {
+        let captures = self.tag_regex.as_ref()?.captures(title)?;
+        let found = captures.get(1).or_else(|| captures.get(0))?;
+        version::parse(found.as_str())
+    }
+}
+
+#[async_trait]
+impl super::ReleaseSource for Source {
+    async fn latest(&self) -> Result<Release> {
+        let mut releases = self.releases(None).await?;
+        releases.sort_by(|a, b| a.detail.published_at.cmp(&b.detail.published_at));
+        releases
+            .pop()
+            .ok_or_else(|| anyhow!("feed {} has no entry", self.url))
+    }
+
+    async fn releases(&self, _since: Option<&str>) -> Result<Vec<Release>> {
+        let request = self.ctx.client.get(&self.url);
+        let body = self
+            .ctx
+            .conditional_send(&self.name, &self.url, request)
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        let feed = feed_rs::parser::parse(body.as_ref()).context("cannot parse the feed")?;
+        debug!("Requested the feed of the {}", self.name);
+
+        let releases: Vec<Release> = feed
+            .entries
+            .into_iter()
+            .filter_map(|v| {
+                // an entry without any date cannot be placed in time
+                let date = v.updated.or(v.published)?;
+                let title = v
+                    .title
+                    .map(|v| v.content.trim().to_string())
+                    .unwrap_or_else(|| v.id.clone());
+                let html_url = v
+                    .links
+                    .into_iter()
+                    .next()
+                    .map_or_else(|| self.url.clone(), |v| v.href);
+                let version = self.version(&title);
+                let detail = ReleaseDetail {
+                    release_name: title.clone(),
+                    prerelease: version.as_ref().is_some_and(|v| !v.pre.is_empty()),
+                    tag_name: title,
+                    published_at: date.to_rfc3339_opts(SecondsFormat::Secs, true),
+                    html_url,
+                };
+                let mut release = Release::new(self.url.clone(), self.name.clone(), detail);
+                release.key = Some(v.id);
+                release.version = version.map(|v| v.to_string());
+                Some(release)
+            })
+            .collect();
+        trace!(
+            "Collected {} entries of the feed of the {}.",
+            releases.len(),
+            self.name
+        );
+        Ok(releases)
+    }
+
+    fn versions_from_tags(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::source::stub::{self, Reply, Stub};
+    use crate::server::source::ReleaseSource;
+    use serde_json::json;
+
+    const RSS: &str = r#"<?xml version="1.0"?>
+<rss version="2.0"><channel>
+  <title>Demo releases</title>
+  <link>https://demo.example/</link>
+  <item>
+    <title>Demo 1.1.0-rc.1 is out</title>
+    <link>https://demo.example/news/1.1.0-rc.1</link>
+    <guid>https://demo.example/news/2</guid>
+    <pubDate>Thu, 01 Feb 2024 00:00:00 GMT</pubDate>
+  </item>
+  <item>
+    <title>Demo 1.0.0 is out</title>
+    <link>https://demo.example/news/1.0.0</link>
+    <guid>https://demo.example/news/1</guid>
+    <pubDate>Mon, 01 Jan 2024 00:00:00 GMT</pubDate>
+  </item>
+</channel></rss>"#;
+
+    const ATOM: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Demo releases</title>
+  <id>urn:demo</id>
+  <updated>2024-02-01T00:00:00Z</updated>
+  <entry>
+    <title>Release v2.0.0-beta.1</title>
+    <id>urn:demo:2</id>
+    <link href="https://demo.example/releases/2"/>
+    <updated>2024-02-01T00:00:00Z</updated>
+  </entry>
+  <entry>
+    <title>Release v1.0.0</title>
+    <id>urn:demo:1</id>
+    <updated>2024-01-01T00:00:00Z</updated>
+  </entry>
+</feed>"#;
+
+    fn source(feed: &Stub, extra: serde_json::Value) -> Source {
+        let mut repo = json!({
+            "name": "demo",
+            "type": "feed",
+            "url": format!("{}/feed", feed.url()),
+        });
+        repo.as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        let repo: Repo = serde_json::from_value(repo).unwrap();
+        Source::new(&repo, stub::context()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn rss_titles_are_no_versions_without_a_tag_regex() {
+        let feed = Stub::start();
+        feed.route("/feed", Reply::json(RSS));
+        let releases = source(&feed, json!({})).releases(None).await.unwrap();
+
+        let found: Vec<(&str, &str)> = releases
+            .iter()
+            .map(|v| (v.id(), v.detail.tag_name.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("https://demo.example/news/2", "Demo 1.1.0-rc.1 is out"),
+                ("https://demo.example/news/1", "Demo 1.0.0 is out"),
+            ]
+        );
+        assert_eq!(releases[0].version, None);
+        assert!(!releases[0].detail.prerelease);
+        assert_eq!(releases[0].detail.published_at, "2024-02-01T00:00:00Z");
+        assert_eq!(
+            releases[0].detail.html_url,
+            "https://demo.example/news/1.1.0-rc.1"
+        );
+    }
+
+    #[tokio::test]
+    async fn atom_versions_are_taken_with_the_tag_regex() {
+        let feed = Stub::start();
+        feed.route("/feed", Reply::json(ATOM));
+        let source = source(&feed, json!({ "tagRegex": r"^Release v(\S+)$" }));
+        let releases = source.releases(None).await.unwrap();
+
+        let found: Vec<(&str, Option<&str>, bool)> = releases
+            .iter()
+            .map(|v| (v.id(), v.version.as_deref(), v.detail.prerelease))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("urn:demo:2", Some("2.0.0-beta.1"), true),
+                ("urn:demo:1", Some("1.0.0"), false),
+            ]
+        );
+        // an entry without a link points to the feed
+        assert_eq!(releases[1].detail.html_url, format!("{}/feed", feed.url()));
+        assert_eq!(source.latest().await.unwrap().id(), "urn:demo:2");
+        assert!(!source.versions_from_tags());
+    }
+
+    #[test]
+    fn feed_needs_a_url() {
+        let repo: Repo = serde_json::from_value(json!({ "name": "demo", "type": "feed" })).unwrap();
+        assert!(Source::new(&repo, stub::context()).is_err());
+    }
+}
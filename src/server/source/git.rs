@@ -0,0 +1,282 @@
+use crate::config::Repo;
+use crate::db::{Release, ReleaseDetail};
+use crate::server::version;
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{SecondsFormat, Utc};
+use log::{debug, trace};
+use reqwest::header;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Lists the tags of any git repository served over http, the way
+/// `git ls-remote --tags` does, through the ref advertisement of the smart
+/// http protocol. Servers only speaking the dumb protocol are understood too.
+pub struct Source {
+    name: String,
+    url: String,
+    // the protocol carries no dates, so a tag is dated when it is first found
+    first_seen: Mutex<HashMap<String, String>>,
+    ctx: super::Context,
+}
+
+impl Source {
+    pub fn new(repo: &Repo, ctx: super::Context) -> Source {
+        Source {
+            name: repo.name.clone(),
+            url: repo.url.trim_end_matches('/').to_string(),
+            first_seen: Default::default(),
+            ctx,
+        }
+    }
+
+    fn refs_url(&self) -> String {
+        format!("{}/info/refs?service=git-upload-pack", self.url)
+    }
+}
+
+#[async_trait]
+impl super::ReleaseSource for Source {
+    async fn latest(&self) -> Result<Release> {
+        self.releases(None)
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow!("repo {} has no tag", self.name))
+    }
+
+    async fn releases(&self, _since: Option<&str>) -> Result<Vec<Release>> {
+        let url = self.refs_url();
+        let request = self.ctx.client.get(&url);
+        let resp = self
+            .ctx
+            .conditional_send(&self.name, &url, request)
+            .await?
+            .error_for_status()?;
+        let smart = resp
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with(SMART_CONTENT_TYPE));
+        let body = resp.bytes().await?;
+        debug!("Requested the refs of the {}", self.name);
+
+        let mut tags = if smart {
+            parse_advertisement(&body).context("cannot parse the ref advertisement")?
+        } else {
+            parse_dumb_refs(&String::from_utf8_lossy(&body))
+        };
+        trace!("The {} advertises {} tags.", self.name, tags.len());
+
+        // order the tags by version when there are versions and keep the
+        // newest. Otherwise the name is all there is to go by, which keeps
+        // the pick the same from poll to poll and date-stamped tags in order
+        if tags.iter().any(|v| version::parse(v).is_some()) {
+            tags.retain(|v| version::parse(v).is_some());
+            tags.sort_by_cached_key(|v| version::parse(v));
+        } else {
+            tags.sort();
+        }
+        tags.drain(..tags.len().saturating_sub(GIT_TAG_LIMIT));
+
+        let now = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+        let mut first_seen = self.first_seen.lock().unwrap();
+        first_seen.retain(|k, _| tags.contains(k));
+        let releases = tags
+            .into_iter()
+            .map(|v| {
+                let published_at = first_seen
+                    .entry(v.clone())
+                    .or_insert_with(|| now.clone())
+                    .clone();
+                let detail = ReleaseDetail {
+                    release_name: v.clone(),
+                    prerelease: version::parse(&v).is_some_and(|v| !v.pre.is_empty()),
+                    tag_name: v,
+                    published_at,
+                    html_url: self.url.clone(),
+                };
+                Release::new(self.url.clone(), self.name.clone(), detail)
+            })
+            .collect();
+        Ok(releases)
+    }
+}
+
+/// Collect the tag names of a smart http ref advertisement, a sequence of
+/// pkt-lines: four hex digits giving the line length, then the payload.
+fn parse_advertisement(body: &[u8]) -> Result<Vec<String>> {
+    let mut tags = Vec::new();
+    let mut rest = body;
+    while rest.len() >= 4 {
+        let len = std::str::from_utf8(&rest[..4])
+            .ok()
+            .and_then(|v| usize::from_str_radix(v, 16).ok())
+            .ok_or_else(|| anyhow!("invalid pkt-line length"))?;
+        // flush-pkt, it separates the service header from the refs
+        if len == 0 {
+            rest = &rest[4..];
+            continue;
+        }
+        if len < 4 || len > rest.len() {
+            return Err(anyhow!("truncated pkt-line"));
+        }
+        let line = String::from_utf8_lossy(&rest[4..len]);
+        rest = &rest[len..];
+
+        let line = line.trim_end_matches('\n');
+        if line.starts_with('#') {
+            continue;
+        }
+        // the first ref carries the capability list after a NUL byte
+        let line = line.split('\0').next().unwrap_or_default();
+        if let Some((_, name)) = line.split_once(' ') {
+            push_tag(&mut tags, name);
+        }
+    }
+    Ok(tags)
+}
+
+/// Collect the tag names of a dumb http `info/refs` file, one
+/// `<sha>\t<ref>` per line.
+fn parse_dumb_refs(body: &str) -> Vec<String> {
+    let mut tags = Vec::new();
+    for line in body.lines() {
+        if let Some((_, name)) = line.split_once('\t') {
+            push_tag(&mut tags, name);
+        }
+    }
+    tags
+}
+
+fn push_tag(tags: &mut Vec<String>, name: &str) {
+    let Some(tag) = name.strip_prefix("refs/tags/") else {
+        return;
+    };
+    // an annotated tag is advertised again peeled to its commit
+    let tag = tag.strip_suffix("^{}").unwrap_or(tag);
+    if !tags.iter().any(|v| v == tag) {
+        tags.push(tag.to_string());
+    }
+}
+
+const SMART_CONTENT_TYPE: &str = "application/x-git-upload-pack-advertisement";
+// how many of the newest tags are turned into releases each poll
+const GIT_TAG_LIMIT: usize = 10;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::source::stub::{self, Reply, Stub};
+    use crate::server::source::ReleaseSource;
+    use serde_json::json;
+
+    // frame a payload as a pkt-line
+    fn pkt(payload: &str) -> String {
+        format!("{:04x}{}", payload.len() + 4, payload)
+    }
+
+    fn advertisement(refs: &[&str]) -> String {
+        let mut body = pkt("# service=git-upload-pack\n") + "0000";
+        for (i, name) in refs.iter().enumerate() {
+            let sha = format!("{:040x}", i + 1);
+            body += &match i {
+                0 => pkt(&format!(
+                    "{} {}\0multi_ack side-band-64k symref=HEAD:refs/heads/main\n",
+                    sha, name
+                )),
+                _ => pkt(&format!("{} {}\n", sha, name)),
+            };
+        }
+        body + "0000"
+    }
+
+    #[test]
+    fn advertisement_lists_each_tag_once() {
+        let body = advertisement(&[
+            "HEAD",
+            "refs/heads/main",
+            "refs/pull/1/head",
+            "refs/tags/v1.0.0",
+            "refs/tags/v1.0.0^{}",
+            "refs/tags/v1.1.0",
+            "refs/tags/nightly",
+            "refs/tags/nightly^{}",
+        ]);
+        let tags = parse_advertisement(body.as_bytes()).unwrap();
+        assert_eq!(tags, ["v1.0.0", "v1.1.0", "nightly"]);
+    }
+
+    #[test]
+    fn advertisement_of_a_repo_without_refs() {
+        // an empty repo only advertises its capabilities
+        let body = pkt("# service=git-upload-pack\n")
+            + "0000"
+            + &pkt(&format!(
+                "{} capabilities^{{}}\0agent=git/2\n",
+                "0".repeat(40)
+            ))
+            + "0000";
+        assert!(parse_advertisement(body.as_bytes()).unwrap().is_empty());
+        assert!(parse_advertisement(b"").unwrap().is_empty());
+        assert!(parse_advertisement(b"0000").unwrap().is_empty());
+    }
+
+    #[test]
+    fn malformed_advertisement_is_an_error() {
+        let body = advertisement(&["refs/tags/v1.0.0"]);
+        // a length running past the end of the body
+        let truncated = &body[..body.len() - 10];
+        assert!(parse_advertisement(truncated.as_bytes()).is_err());
+        assert!(parse_advertisement(b"00zz# service").is_err());
+        // lengths 1 to 3 cannot even hold themselves
+        assert!(parse_advertisement(b"0003abc").is_err());
+    }
+
+    #[test]
+    fn dumb_refs_list_each_tag_once() {
+        let body = "1111\trefs/heads/main\n\
+            2222\trefs/tags/v0.9\n\
+            3333\trefs/tags/v0.9^{}\n\
+            4444\trefs/tags/v1.0\n";
+        assert_eq!(parse_dumb_refs(body), ["v0.9", "v1.0"]);
+        assert!(parse_dumb_refs("").is_empty());
+    }
+
+    #[tokio::test]
+    async fn tags_without_versions_keep_their_order_and_dates() {
+        let server = Stub::start();
+        let refs = |names: &[&str]| {
+            let names: Vec<String> = names.iter().map(|v| format!("refs/tags/{}", v)).collect();
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            let mut reply = Reply::json(&advertisement(&names));
+            reply.headers = vec![("Content-Type".to_string(), SMART_CONTENT_TYPE.to_string())];
+            reply
+        };
+        let repo: Repo = serde_json::from_value(json!({
+            "name": "repo",
+            "type": "git",
+            "url": format!("{}/repo.git", server.url()),
+        }))
+        .unwrap();
+        let source = Source::new(&repo, stub::context());
+
+        let path = "/repo.git/info/refs";
+        server.route(
+            path,
+            refs(&["build-2024-03-01", "build-2024-01-15", "build-2024-02-10"]),
+        );
+        let first = source.releases(None).await.unwrap();
+        let tags: Vec<&str> = first.iter().map(|v| v.detail.tag_name.as_str()).collect();
+        assert_eq!(
+            tags,
+            ["build-2024-01-15", "build-2024-02-10", "build-2024-03-01"]
+        );
+
+        server.route(
+            path,
+            refs(&["build-2024-03-01", "build-2024-01-15", "build-2024-02-10"]),
+        );
+        let second = source.releases(None).await.unwrap();
+        assert_eq!(first, second);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::config::Repo;
+use crate::db::{Release, ReleaseDetail};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest::header;
+use reqwest::Response;
+use serde::Deserialize;
+
+/// Watches the releases of a repo on a Gitea compatible instance, such as
+/// Forgejo or Codeberg, through the `/api/v1` REST API.
+pub struct Source {
+    name: String,
+    // the instance, e.g. https://codeberg.org
+    base: String,
+    owner: String,
+    repo: String,
+    token: Option<String>,
+    ctx: super::Context,
+}
+
+#[derive(Debug, Deserialize)]
+struct GiteaRelease {
+    #[serde(default)]
+    name: String,
+    tag_name: String,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    prerelease: bool,
+    published_at: Option<String>,
+    html_url: String,
+}
+
+impl Source {
+    pub fn new(repo: &Repo, ctx: super::Context) -> Result<Source> {
+        let (owner, name) = repo
+            .project
+            .as_deref()
+            .and_then(|v| v.trim_matches('/').split_once('/'))
+            .filter(|(owner, name)| !owner.is_empty() && !name.is_empty())
+            .ok_or_else(|| anyhow!("repo {} needs a gitea project like owner/repo", repo.name))?;
+        Ok(Source {
+            name: repo.name.clone(),
+            base: super::base_url(repo, GITEA_URL),
+            owner: owner.to_string(),
+            repo: name.to_string(),
+            token: repo.token.clone(),
+            ctx,
+        })
+    }
+
+    fn list_url(&self) -> String {
+        format!(
+            "{}/api/v1/repos/{}/{}/releases?limit={}",
+            self.base,
+            super::encode_component(&self.owner),
+            super::encode_component(&self.repo),
+            GITEA_PER_PAGE
+        )
+    }
+
+    async fn get(&self, url: &str, conditional: bool) -> Result<Response> {
+        let mut request = self.ctx.client.get(url);
+        if let Some(token) = &self.token {
+            request = request.header(header::AUTHORIZATION, format!("token {}", token));
+        }
+        let resp = if conditional {
+            self.ctx.conditional_send(&self.name, url, request).await?
+        } else {
+            request.send().await?
+        };
+        Ok(resp.error_for_status()?)
+    }
+}
+
+#[async_trait]
+impl super::ReleaseSource for Source {
+    async fn latest(&self) -> Result<Release> {
+        let mut releases = self.releases(None).await?;
+        releases.sort_by(|a, b| a.detail.published_at.cmp(&b.detail.published_at));
+        releases
+            .pop()
+            .ok_or_else(|| anyhow!("repo {}/{} has no release", self.owner, self.repo))
+    }
+
+    async fn releases(&self, since: Option<&str>) -> Result<Vec<Release>> {
+        let what = format!("releases of the {}", self.name);
+        let pages: Vec<Vec<GiteaRelease>> = super::paginate(
+            &what,
+            self.list_url(),
+            GITEA_MAX_PAGES,
+            |url, conditional| async move { self.get(&url, conditional).await },
+            |page: &Vec<GiteaRelease>| {
+                !page.iter().filter(|v| !v.draft).any(|v| {
+                    since.is_some_and(|s| v.published_at.as_deref().is_some_and(|d| d <= s))
+                })
+            },
+        )
+        .await?;
+
+        let releases = pages
+            .into_iter()
+            .flatten()
+            // drafts are only visible to maintainers and have no publish time
+            .filter(|v| !v.draft)
+            .filter_map(|v| {
+                let detail = ReleaseDetail {
+                    release_name: if v.name.is_empty() {
+                        v.tag_name.clone()
+                    } else {
+                        v.name
+                    },
+                    tag_name: v.tag_name,
+                    prerelease: v.prerelease,
+                    published_at: v.published_at?,
+                    html_url: v.html_url,
+                };
+                Some(Release::new(self.base.clone(), self.name.clone(), detail))
+            })
+            .collect();
+        Ok(releases)
+    }
+}
+
+const GITEA_URL: &str = "https://codeberg.org";
+const GITEA_PER_PAGE: u8 = 30;
+const GITEA_MAX_PAGES: u8 = 5;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::source::stub::{self, Reply, Stub};
+    use crate::server::source::ReleaseSource;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn drafts_are_skipped_and_untitled_releases_named_by_tag() {
+        let gitea = Stub::start();
+        let releases = json!([
+            {
+                "name": "",
+                "tag_name": "v1.2.0",
+                "draft": true,
+                "published_at": null,
+                "html_url": "https://codeberg.org/owner/repo/releases/tag/v1.2.0",
+            },
+            {
+                "name": "",
+                "tag_name": "v1.1.0",
+                "prerelease": true,
+                "published_at": "2024-02-01T00:00:00Z",
+                "html_url": "https://codeberg.org/owner/repo/releases/tag/v1.1.0",
+            },
+            {
+                "name": "First",
+                "tag_name": "v1.0.0",
+                "published_at": "2024-01-01T00:00:00Z",
+                "html_url": "https://codeberg.org/owner/repo/releases/tag/v1.0.0",
+            },
+        ]);
+        gitea.route(
+            "/api/v1/repos/owner/repo/releases",
+            Reply::json(&releases.to_string()),
+        );
+        let repo: Repo = serde_json::from_value(json!({
+            "name": "repo",
+            "type": "gitea",
+            "url": gitea.url(),
+            "project": "owner/repo",
+            "token": "secret",
+        }))
+        .unwrap();
+        let source = Source::new(&repo, stub::context()).unwrap();
+
+        let releases = source.releases(None).await.unwrap();
+        let tags: Vec<&str> = releases
+            .iter()
+            .map(|v| v.detail.tag_name.as_str())
+            .collect();
+        assert_eq!(tags, ["v1.1.0", "v1.0.0"]);
+        assert_eq!(releases[0].detail.release_name, "v1.1.0");
+        assert!(releases[0].detail.prerelease);
+        assert_eq!(releases[1].detail.release_name, "First");
+
+        let received = gitea.received();
+        assert_eq!(
+            received[0].target,
+            format!("/api/v1/repos/owner/repo/releases?limit={}", GITEA_PER_PAGE)
+        );
+        assert_eq!(received[0].header("Authorization"), Some("token secret"));
+    }
+}
@@ -0,0 +1,174 @@
+use super::app::{App, AppConfig};
+use super::pool::{TokenPool, TokenSource};
+use super::RateLimit;
+use anyhow::{Context, Result};
+use reqwest::header::HeaderValue;
+use reqwest::{Client, StatusCode, Url};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// The Authorization header to send to each GitHub host, so that github.com
+/// and GitHub Enterprise servers can be watched at once.
+#[derive(Clone, Default)]
+pub struct Credentials {
+    // only sent to github.com, other hosts never see these tokens
+    default: TokenPool,
+    hosts: HashMap<String, HeaderValue>,
+    // takes precedence over the static credentials of its host
+    app: Option<Arc<App>>,
+}
+
+/// The credentials chosen for one request.
+#[derive(Debug, Clone)]
+pub struct Authorization {
+    pub header: Option<HeaderValue>,
+    // the rate limit bucket the request counts against
+    pub bucket: String,
+    // the token of the default pool that was used
+    token: Option<usize>,
+}
+
+impl Credentials {
+    pub fn new(
+        default: &str,
+        tokens: &[TokenSource],
+        hosts: &HashMap<String, String>,
+        app: Option<&AppConfig>,
+    ) -> Result<Credentials> {
+        let mut credentials = Credentials {
+            default: TokenPool::new(default, tokens)?,
+            hosts: HashMap::new(),
+            app: app.map(App::new).transpose()?.map(Arc::new),
+        };
+        for (host, v) in hosts.iter() {
+            let value = v
+                .parse::<HeaderValue>()
+                .with_context(|| format!("invalid credentials of {}", host))?;
+            credentials.hosts.insert(normalize(host), value);
+        }
+        Ok(credentials)
+    }
+
+    /// The Authorization header for a request to `url`. A GitHub App may
+    /// have to fetch a new installation token first, and the default tokens
+    /// are chosen by the quota they have left. A host other than github.com
+    /// without credentials of its own is sent none.
+    pub async fn authorization(
+        &self,
+        client: &Client,
+        limit: &RateLimit,
+        url: &str,
+    ) -> Result<Authorization> {
+        let host = host_of(url);
+        if let Some(app) = self.app.as_ref().filter(|v| v.host() == host) {
+            return Ok(Authorization {
+                header: Some(app.authorization(client).await?),
+                bucket: host,
+                token: None,
+            });
+        }
+        if let Some(v) = self.hosts.get(&host) {
+            return Ok(Authorization {
+                header: Some(v.clone()),
+                bucket: host,
+                token: None,
+            });
+        }
+        if host != GITHUB_HOST {
+            return Ok(Authorization {
+                header: None,
+                bucket: host,
+                token: None,
+            });
+        }
+        Ok(match self.default.pick(&host, limit) {
+            Some((i, header, bucket)) => Authorization {
+                header: Some(header),
+                bucket,
+                token: Some(i),
+            },
+            None => Authorization {
+                header: None,
+                bucket: host,
+                token: None,
+            },
+        })
+    }
+
+    /// Record whether the credentials of a request were accepted, setting a
+    /// pooled token aside while it is rejected with 401.
+    pub fn report(&self, auth: &Authorization, status: StatusCode) {
+        let Some(i) = auth.token else {
+            return;
+        };
+        if status == StatusCode::UNAUTHORIZED {
+            self.default.quarantine(i);
+        } else {
+            self.default.restore(i);
+        }
+    }
+}
+
+/// The GitHub host a url points to, which also names its rate limit.
+/// `api.github.com` is reported as `github.com`.
+pub fn host_of(url: &str) -> String {
+    let Ok(url) = Url::parse(url) else {
+        return url.to_string();
+    };
+    let host = normalize(url.host_str().unwrap_or_default());
+    match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host,
+    }
+}
+
+fn normalize(host: &str) -> String {
+    let host = host.trim().trim_end_matches('/').to_ascii_lowercase();
+    let host = host
+        .strip_prefix("https://")
+        .or_else(|| host.strip_prefix("http://"))
+        .unwrap_or(&host);
+    match host {
+        "api.github.com" => GITHUB_HOST.to_string(),
+        _ => host.to_string(),
+    }
+}
+
+const GITHUB_HOST: &str = "github.com";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn default_token_is_only_sent_to_github() {
+        let hosts = HashMap::from([(
+            "https://ghe.example.com/".to_string(),
+            "token ghe".to_string(),
+        )]);
+        let credentials = Credentials::new("token default", &[], &hosts, None).unwrap();
+        let (client, limit) = (Client::new(), RateLimit::default());
+        let header = |url: &'static str| {
+            let (credentials, client, limit) = (&credentials, &client, &limit);
+            async move {
+                let auth = credentials.authorization(client, limit, url).await.unwrap();
+                (
+                    auth.header.map(|v| v.to_str().unwrap().to_string()),
+                    auth.bucket,
+                )
+            }
+        };
+
+        let (auth, bucket) = header("https://api.github.com/repos/a/b/releases").await;
+        assert_eq!(auth.as_deref(), Some("token default"));
+        assert!(bucket.starts_with("github.com"));
+        assert_eq!(
+            header("https://ghe.example.com/api/v3/repos/a/b").await,
+            (Some("token ghe".to_string()), "ghe.example.com".to_string())
+        );
+        assert_eq!(
+            header("https://git.example.org/api/v3/repos/a/b").await,
+            (None, "git.example.org".to_string())
+        );
+    }
+}
@@ -0,0 +1,271 @@
+use super::tag::{newest_tags, tag_detail};
+use super::{headers, RepoPath};
+use crate::db::{Release, ReleaseDetail};
+use crate::server::source::Context;
+use anyhow::{anyhow, Result};
+use chrono::DateTime;
+use log::{debug, error, info, trace, warn};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// A watched repo to fetch in a batch: its name, configured url and
+/// whether its tags are watched instead of its releases.
+pub struct Target {
+    pub name: String,
+    pub url: String,
+    pub tags: bool,
+}
+
+/// Releases fetched ahead of the pullers by batched GraphQL queries, keyed
+/// by repo name. Each entry is handed out once, so a retrying puller falls
+/// back to the REST API.
+#[derive(Debug, Clone, Default)]
+pub struct Prefetch {
+    releases: Arc<Mutex<HashMap<String, Vec<Release>>>>,
+}
+
+impl Prefetch {
+    pub fn take(&self, name: &str) -> Option<Vec<Release>> {
+        self.releases.lock().unwrap().remove(name)
+    }
+
+    fn put(&self, name: String, releases: Vec<Release>) {
+        self.releases.lock().unwrap().insert(name, releases);
+    }
+
+    fn clear(&self) {
+        self.releases.lock().unwrap().clear();
+    }
+}
+
+/// Fetch the recent releases of every target with one GraphQL query per
+/// `GRAPHQL_BATCH_SIZE` repos. Repos that cannot be fetched this way are
+/// left to the REST API.
+pub async fn prefetch(ctx: &Context, targets: Vec<Target>) {
+    ctx.github_prefetch.clear();
+    let mut batches: HashMap<String, Vec<(RepoPath, Target)>> = HashMap::new();
+    for v in targets.into_iter() {
+        match RepoPath::from_url(&v.url) {
+            Some(path) => batches
+                .entry(path.graphql_url())
+                .or_default()
+                .push((path, v)),
+            None => debug!(
+                "Repo: {} url is not a GitHub repo url, skip it in the GraphQL batch",
+                v.name
+            ),
+        }
+    }
+
+    for (endpoint, targets) in batches.iter() {
+        for chunk in targets.chunks(GRAPHQL_BATCH_SIZE) {
+            match query(ctx, endpoint, chunk).await {
+                Ok(n) => info!(
+                    "Fetched the releases of {}/{} repos with one GraphQL query",
+                    n,
+                    chunk.len()
+                ),
+                Err(e) => error!(
+                    "GraphQL query to {} failed, fall back to the REST API. Error: {}",
+                    endpoint, e
+                ),
+            }
+        }
+    }
+}
+
+async fn query(ctx: &Context, endpoint: &str, chunk: &[(RepoPath, Target)]) -> Result<usize> {
+    let mut params = Vec::new();
+    let mut fields = Vec::new();
+    let mut variables = Map::new();
+    for (i, (path, target)) in chunk.iter().enumerate() {
+        let fragment = if target.tags { "tags" } else { "releases" };
+        params.push(format!("$o{0}: String!, $n{0}: String!", i));
+        fields.push(format!(
+            "r{0}: repository(owner: $o{0}, name: $n{0}) {{ ...{1} }}",
+            i, fragment
+        ));
+        variables.insert(format!("o{}", i), json!(path.owner));
+        variables.insert(format!("n{}", i), json!(path.repo));
+    }
+    // graphql rejects fragments that are defined but not used
+    let mut fragments = Vec::new();
+    if chunk.iter().any(|(_, v)| !v.tags) {
+        fragments.push(RELEASES_FRAGMENT);
+    }
+    if chunk.iter().any(|(_, v)| v.tags) {
+        fragments.push(TAGS_FRAGMENT);
+    }
+    let query = format!(
+        "query({}) {{ {} rateLimit {{ cost limit remaining resetAt }} }} {}",
+        params.join(", "),
+        fields.join(" "),
+        fragments.join(" ")
+    );
+    trace!("GraphQL query: {}", query);
+
+    let limit = &ctx.github_rate_limit;
+    let (headers, auth) = headers(ctx, endpoint).await?;
+    // queries are charged in points against a quota of their own
+    let bucket = format!("{} graphql", auth.bucket);
+    limit.acquire(&bucket).await;
+    let resp = ctx
+        .client
+        .post(endpoint)
+        .headers(headers)
+        .json(&json!({ "query": query, "variables": variables }))
+        .send()
+        .await?;
+    ctx.github_credentials.report(&auth, resp.status());
+    limit.update(&bucket, &resp)?;
+    let resp = resp.error_for_status()?;
+    let mut body: Value = resp.json().await?;
+
+    if let Some(errors) = body.get("errors").and_then(Value::as_array) {
+        for v in errors.iter() {
+            warn!("GraphQL query reported an error: {}", v["message"]);
+        }
+    }
+    let mut data = match body.get_mut("data").map(Value::take) {
+        Some(Value::Object(v)) => v,
+        _ => return Err(anyhow!("GraphQL response has no data")),
+    };
+    if let Some(v) = data.remove("rateLimit") {
+        debug!("GraphQL rate limit: {}", v);
+        let reset = v["resetAt"]
+            .as_str()
+            .and_then(|v| DateTime::parse_from_rfc3339(v).ok())
+            .and_then(|v| u64::try_from(v.timestamp()).ok());
+        if let (Some(remaining), Some(reset)) = (v["remaining"].as_u64(), reset) {
+            limit.record(&bucket, v["limit"].as_u64(), remaining, reset);
+        }
+    }
+
+    let mut fetched = 0;
+    for (i, (path, target)) in chunk.iter().enumerate() {
+        let Some(repo) = data.remove(&format!("r{}", i)).filter(|v| !v.is_null()) else {
+            continue;
+        };
+        let details: Vec<ReleaseDetail> = if target.tags {
+            let mut nodes: Vec<TagNode> = serde_json::from_value(repo["refs"]["nodes"].clone())?;
+            // the same tags the REST API path would date
+            newest_tags(&mut nodes, |v| &v.name);
+            nodes
+                .into_iter()
+                .filter_map(|v| {
+                    let date = v.target.committed_date()?;
+                    Some(tag_detail(path, &v.name, date))
+                })
+                .collect()
+        } else {
+            let nodes: Vec<Node> = serde_json::from_value(repo["releases"]["nodes"].clone())?;
+            nodes.into_iter().filter_map(|v| v.into_detail()).collect()
+        };
+        let releases = details
+            .into_iter()
+            .map(|v| Release::new(target.url.clone(), target.name.clone(), v))
+            .collect();
+        ctx.github_prefetch.put(target.name.clone(), releases);
+        fetched += 1;
+    }
+    Ok(fetched)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Node {
+    name: Option<String>,
+    tag_name: String,
+    is_prerelease: bool,
+    is_draft: bool,
+    published_at: Option<String>,
+    url: String,
+}
+
+impl Node {
+    // the same shape the REST API returns, drafts are never published
+    fn into_detail(self) -> Option<ReleaseDetail> {
+        if self.is_draft {
+            return None;
+        }
+        Some(ReleaseDetail {
+            release_name: self.name.unwrap_or_else(|| self.tag_name.clone()),
+            tag_name: self.tag_name,
+            prerelease: self.is_prerelease,
+            published_at: self.published_at?,
+            html_url: self.url,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TagNode {
+    name: String,
+    target: TagTarget,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TagTarget {
+    committed_date: Option<String>,
+    // set when the ref is an annotated tag pointing to a commit
+    target: Option<Box<TagTarget>>,
+}
+
+impl TagTarget {
+    fn committed_date(self) -> Option<String> {
+        match self.committed_date {
+            Some(v) => Some(v),
+            None => self.target?.committed_date(),
+        }
+    }
+}
+
+const RELEASES_FRAGMENT: &str = "fragment releases on Repository { \
+    releases(first: 30, orderBy: {field: CREATED_AT, direction: DESC}) { \
+    nodes { name tagName isPrerelease isDraft publishedAt url } } }";
+const TAGS_FRAGMENT: &str = "fragment tags on Repository { \
+    refs(refPrefix: \"refs/tags/\", first: 30, \
+    orderBy: {field: TAG_COMMIT_DATE, direction: DESC}) { \
+    nodes { name target { ... on Commit { committedDate } \
+    ... on Tag { target { ... on Commit { committedDate } } } } } } }";
+const GRAPHQL_BATCH_SIZE: usize = 50;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::source::stub::{self, Reply, Stub};
+
+    #[tokio::test]
+    async fn prefetched_tags_keep_the_newest_versions() {
+        let github = Stub::start();
+        let tag = |name: &str, date: &str| json!({ "name": name, "target": { "committedDate": format!("{}T00:00:00Z", date) } });
+        let body = json!({
+            "data": {
+                "r0": { "refs": { "nodes": [
+                    tag("nightly", "2024-03-01"),
+                    tag("v1.2.0", "2024-02-01"),
+                    tag("v1.10.0", "2024-01-01"),
+                ] } },
+            },
+        });
+        github.route("/graphql", Reply::json(&body.to_string()));
+        let ctx = stub::context();
+        let target = Target {
+            name: "repo".to_string(),
+            url: format!("{}/repos/owner/repo/releases/latest", github.url()),
+            tags: true,
+        };
+        prefetch(&ctx, vec![target]).await;
+
+        let releases = ctx.github_prefetch.take("repo").unwrap();
+        let tags: Vec<&str> = releases
+            .iter()
+            .map(|v| v.detail.tag_name.as_str())
+            .collect();
+        assert_eq!(tags, ["v1.10.0", "v1.2.0"]);
+        assert_eq!(github.requests(), ["POST /graphql"]);
+    }
+}
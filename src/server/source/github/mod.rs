@@ -0,0 +1,207 @@
+mod app;
+mod credentials;
+pub mod graphql;
+mod pool;
+mod rate_limit;
+mod tag;
+use crate::config::Repo;
+use crate::db::{Release, ReleaseDetail};
+use anyhow::{anyhow, Context, Result};
+pub use app::AppConfig;
+use async_trait::async_trait;
+pub use credentials::{host_of, Authorization, Credentials};
+pub use graphql::Prefetch;
+use log::{debug, trace};
+pub use pool::TokenSource;
+pub use rate_limit::{Budget, RateLimit};
+use reqwest::header::{self, HeaderMap};
+use reqwest::Response;
+use serde_json::Value;
+pub use tag::TagSource;
+
+pub struct Source {
+    name: String,
+    url: String,
+    ctx: super::Context,
+}
+
+/// Where a repo lives: the REST API base url, the owner and the repo name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoPath {
+    pub api: String,
+    pub owner: String,
+    pub repo: String,
+}
+
+impl RepoPath {
+    /// Split an API url like `https://api.github.com/repos/{owner}/{repo}/...`
+    /// (or `https://{host}/api/v3/repos/...` on GitHub Enterprise).
+    pub fn from_url(url: &str) -> Option<RepoPath> {
+        let (api, path) = url.split_once("/repos/")?;
+        let mut parts = path.split('/');
+        let owner = parts.next().filter(|v| !v.is_empty())?;
+        let repo = parts.next().filter(|v| !v.is_empty())?;
+        Some(RepoPath {
+            api: api.to_string(),
+            owner: owner.to_string(),
+            repo: repo.to_string(),
+        })
+    }
+
+    /// The web url of the repo, e.g. `https://github.com/{owner}/{repo}`.
+    pub fn html_url(&self) -> String {
+        let host = match self.api.strip_suffix("/api/v3") {
+            Some(v) => v.to_string(),
+            None => self.api.replacen("://api.", "://", 1),
+        };
+        format!("{}/{}/{}", host, self.owner, self.repo)
+    }
+
+    pub fn graphql_url(&self) -> String {
+        match self.api.strip_suffix("/v3") {
+            Some(v) => format!("{}/graphql", v),
+            None => format!("{}/graphql", self.api),
+        }
+    }
+}
+
+/// The REST API base url of a GitHub host: github.com when no host is
+/// given, otherwise a GitHub Enterprise server.
+pub fn api_base(host: Option<&str>) -> String {
+    let host = host.map(|v| v.trim().trim_end_matches('/'));
+    match host {
+        None | Some("") | Some("github.com") | Some("api.github.com") => GITHUB_API_URL.to_string(),
+        Some(v) if v.contains("://") => format!("{}/api/v3", v),
+        Some(v) => format!("https://{}/api/v3", v),
+    }
+}
+
+/// The API url of the latest release of a repo: the configured url, or one
+/// built from its `owner/repo` and host.
+pub fn repo_url(repo: &Repo) -> Result<String> {
+    if !repo.url.is_empty() {
+        return Ok(repo.url.clone());
+    }
+    let (owner, name) = repo
+        .project
+        .as_deref()
+        .and_then(|v| v.trim_matches('/').split_once('/'))
+        .filter(|(owner, name)| !owner.is_empty() && !name.is_empty())
+        .ok_or_else(|| anyhow!("repo {} needs a url or an owner/repo project", repo.name))?;
+    Ok(format!(
+        "{}/repos/{}/{}/releases/latest",
+        api_base(repo.host.as_deref()),
+        owner,
+        name
+    ))
+}
+
+impl Source {
+    pub fn new(repo: &Repo, ctx: super::Context) -> Result<Source> {
+        Ok(Source {
+            name: repo.name.clone(),
+            url: repo_url(repo)?,
+            ctx,
+        })
+    }
+
+    /// The `/releases` listing endpoint of the configured repo url, which is
+    /// usually given as `.../releases/latest`.
+    fn list_url(&self) -> String {
+        let url = self.url.trim_end_matches('/');
+        let url = url.strip_suffix("/latest").unwrap_or(url);
+        format!("{}?per_page={}", url, RELEASE_PER_PAGE)
+    }
+}
+
+#[async_trait]
+impl super::ReleaseSource for Source {
+    async fn latest(&self) -> Result<Release> {
+        let resp = get(&self.ctx, &self.name, &self.url, false)
+            .await?
+            .text()
+            .await?;
+        debug!("Requested the latest release version of the {}", self.name);
+        let detail: ReleaseDetail =
+            serde_json::from_str(resp.as_str()).context("Deserialize http response failed!")?;
+        trace!("Deserialized the http response to crate::db::ReleaseDetail.");
+
+        Ok(Release::new(self.url.clone(), self.name.clone(), detail))
+    }
+
+    async fn releases(&self, since: Option<&str>) -> Result<Vec<Release>> {
+        if let Some(releases) = self.ctx.github_prefetch.take(&self.name) {
+            trace!(
+                "Use the {} releases of the {} fetched by GraphQL.",
+                releases.len(),
+                self.name
+            );
+            return Ok(releases);
+        }
+        let what = format!("releases of the {}", self.name);
+        let pages: Vec<Vec<Value>> = super::paginate(
+            &what,
+            self.list_url(),
+            RELEASE_MAX_PAGES,
+            |url, conditional| async move { get(&self.ctx, &self.name, &url, conditional).await },
+            |page: &Vec<Value>| {
+                !page.iter().any(|v| {
+                    since.is_some_and(|s| v["published_at"].as_str().is_some_and(|d| d <= s))
+                })
+            },
+        )
+        .await?;
+
+        let mut releases = Vec::new();
+        for mut v in pages.into_iter().flatten() {
+            // drafts have no publish time and are only visible to maintainers
+            if v["draft"].as_bool().unwrap_or(false) {
+                continue;
+            }
+            // releases created without a title have `"name": null`
+            if v["name"].is_null() {
+                v["name"] = v["tag_name"].clone();
+            }
+            let detail: ReleaseDetail =
+                serde_json::from_value(v).context("Deserialize http response failed!")?;
+            releases.push(Release::new(self.url.clone(), self.name.clone(), detail));
+        }
+        Ok(releases)
+    }
+}
+
+/// The headers of a request to the GitHub API at `url`, with the
+/// credentials chosen for it.
+async fn headers(ctx: &super::Context, url: &str) -> Result<(HeaderMap, Authorization)> {
+    let auth = ctx
+        .github_credentials
+        .authorization(&ctx.client, &ctx.github_rate_limit, url)
+        .await?;
+    let mut headers = ctx.github_headers.clone();
+    if let Some(v) = auth.header.clone() {
+        headers.insert(header::AUTHORIZATION, v);
+    }
+    Ok((headers, auth))
+}
+
+/// Send a GET to the GitHub API for the repo `name` within the rate limit of
+/// its credentials. With `conditional` set the request reuses the validators
+/// of the last poll.
+async fn get(ctx: &super::Context, name: &str, url: &str, conditional: bool) -> Result<Response> {
+    let limit = &ctx.github_rate_limit;
+    let (headers, auth) = headers(ctx, url).await?;
+    limit.acquire(&auth.bucket).await;
+    let request = ctx.client.get(url).headers(headers);
+    let resp = if conditional {
+        ctx.conditional_send(name, url, request).await?
+    } else {
+        request.send().await?
+    };
+    ctx.github_credentials.report(&auth, resp.status());
+    limit.update(&auth.bucket, &resp)?;
+    Ok(resp.error_for_status()?)
+}
+
+const GITHUB_API_URL: &str = "https://api.github.com";
+const RELEASE_PER_PAGE: u8 = 30;
+const RELEASE_MAX_PAGES: u8 = 5;
@@ -0,0 +1,249 @@
+use super::RateLimit;
+use anyhow::{anyhow, Context, Result};
+use log::{debug, info, warn};
+use reqwest::header::HeaderValue;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where a GitHub token of the pool is read from: the token itself, an
+/// environment variable, or a file holding one token per line.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum TokenSource {
+    Inline(String),
+    Env { env: String },
+    File { file: PathBuf },
+}
+
+/// Tokens that share the requests to a GitHub host. Each request takes the
+/// token with the most quota left, in turns when several have the same.
+/// A token rejected with 401 is set aside and tried again later, so it is
+/// back in use once it has been fixed.
+#[derive(Debug, Clone, Default)]
+pub struct TokenPool {
+    tokens: Arc<Vec<Token>>,
+    next: Arc<AtomicUsize>,
+}
+
+#[derive(Debug)]
+struct Token {
+    // safe to log, see `redact`
+    id: String,
+    value: HeaderValue,
+    // unix time in seconds
+    quarantined_until: Mutex<Option<u64>>,
+}
+
+impl TokenPool {
+    pub fn new(header: &str, sources: &[TokenSource]) -> Result<TokenPool> {
+        let mut values = Vec::new();
+        if !header.is_empty() {
+            values.push(header.to_string());
+        }
+        for source in sources.iter() {
+            match source {
+                TokenSource::Inline(v) => values.push(v.clone()),
+                TokenSource::Env { env: name } => values.push(
+                    env::var(name)
+                        .with_context(|| format!("cannot read the GitHub token from ${}", name))?,
+                ),
+                TokenSource::File { file } => {
+                    let content = fs::read_to_string(file).with_context(|| {
+                        format!("cannot read the GitHub tokens from {}", file.display())
+                    })?;
+                    values.extend(content.lines().map(String::from));
+                }
+            }
+        }
+
+        let mut tokens: Vec<Token> = Vec::new();
+        for v in values.iter().map(|v| v.trim()).filter(|v| !v.is_empty()) {
+            // a bare token or a whole Authorization header value
+            let value = match v.split_once(' ') {
+                Some(_) => v.to_string(),
+                None => format!("token {}", v),
+            };
+            let id = redact(&value);
+            let value = value
+                .parse::<HeaderValue>()
+                .map_err(|_| anyhow!("cannot parse the GitHub token {} to header value", id))?;
+            if tokens.iter().any(|t| t.value == value) {
+                continue;
+            }
+            tokens.push(Token {
+                id,
+                value,
+                quarantined_until: Default::default(),
+            });
+        }
+        if tokens.len() > 1 {
+            info!(
+                "Share the GitHub requests among {} tokens: {}",
+                tokens.len(),
+                tokens
+                    .iter()
+                    .map(|v| v.id.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        Ok(TokenPool {
+            tokens: Arc::new(tokens),
+            next: Default::default(),
+        })
+    }
+
+    /// Choose the token for a request to `host`, returning its index, the
+    /// header value and the rate limit bucket it counts against.
+    pub fn pick(&self, host: &str, limit: &RateLimit) -> Option<(usize, HeaderValue, String)> {
+        let len = self.tokens.len();
+        let now = now();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let mut best: Option<(usize, u64)> = None;
+        // the token released soonest, in case all of them are quarantined
+        let mut fallback: Option<(usize, u64)> = None;
+        for i in (start..start + len).map(|v| v % len) {
+            let token = &self.tokens[i];
+            let quarantined = *token.quarantined_until.lock().unwrap();
+            if let Some(until) = quarantined.filter(|v| *v > now) {
+                if !matches!(fallback, Some((_, v)) if v <= until) {
+                    fallback = Some((i, until));
+                }
+                continue;
+            }
+            let available = limit.available(&bucket(host, &token.id));
+            if !matches!(best, Some((_, v)) if v >= available) {
+                best = Some((i, available));
+            }
+        }
+        let (i, _) = best.or(fallback)?;
+        let token = &self.tokens[i];
+        let bucket = bucket(host, &token.id);
+        debug!("Use the GitHub token {}", bucket);
+        Some((i, token.value.clone(), bucket))
+    }
+
+    /// Set a token aside after it was rejected with 401.
+    pub fn quarantine(&self, i: usize) {
+        let Some(token) = self.tokens.get(i) else {
+            return;
+        };
+        let until = now() + TOKEN_QUARANTINE;
+        let mut quarantined = token.quarantined_until.lock().unwrap();
+        if quarantined.is_some_and(|v| v > now()) {
+            return;
+        }
+        *quarantined = Some(until);
+        warn!(
+            "GitHub token {} was rejected with 401. Set it aside for {} seconds",
+            token.id, TOKEN_QUARANTINE
+        );
+    }
+
+    /// Release a token after a request with it succeeded.
+    pub fn restore(&self, i: usize) {
+        let Some(token) = self.tokens.get(i) else {
+            return;
+        };
+        if token.quarantined_until.lock().unwrap().take().is_some() {
+            info!("GitHub token {} is accepted again", token.id);
+        }
+    }
+}
+
+// tokens have separate quotas, so each gets a bucket of its own
+fn bucket(host: &str, id: &str) -> String {
+    format!("{} ({})", host, id)
+}
+
+/// Shows the kind of a token and a short hash of it, e.g. `ghp_***3fa9c21b`,
+/// which tells tokens apart without giving any of them away.
+fn redact(value: &str) -> String {
+    let token = value.rsplit(' ').next().unwrap_or(value);
+    let kind = match token.find('_') {
+        Some(i) if i <= 10 => &token[..=i],
+        _ => "",
+    };
+    let hash = Sha256::digest(token.as_bytes());
+    let id: String = hash[..4].iter().map(|v| format!("{:02x}", v)).collect();
+    format!("{}***{}", kind, id)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|v| v.as_secs())
+        .unwrap_or(0)
+}
+
+// how long a token rejected with 401 is left out before it is tried again
+const TOKEN_QUARANTINE: u64 = 1800;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(tokens: &[&str]) -> TokenPool {
+        let sources: Vec<TokenSource> = tokens
+            .iter()
+            .map(|v| TokenSource::Inline(v.to_string()))
+            .collect();
+        TokenPool::new("", &sources).unwrap()
+    }
+
+    #[test]
+    fn short_tokens_are_told_apart() {
+        let a = redact("token ghp_short1");
+        let b = redact("token ghp_short2");
+        assert_ne!(a, b);
+        assert!(a.starts_with("ghp_***"), "{}", a);
+        assert!(!a.contains("short"), "{}", a);
+        assert_eq!(a, redact("token ghp_short1"));
+        assert_eq!(redact("Bearer abc"), redact("token abc"));
+        assert!(redact("token abc").starts_with("***"));
+    }
+
+    #[test]
+    fn token_with_most_quota_is_picked() {
+        let pool = pool(&["ghp_aaaaaaaa", "ghp_bbbbbbbb", "ghp_cccccccc"]);
+        let limit = RateLimit::default();
+        let reset = now() + 3600;
+        for (token, remaining) in pool.tokens.iter().zip([100, 4000, 20]) {
+            limit.record(&bucket("api.github.com", &token.id), None, remaining, reset);
+        }
+        for _ in 0..3 {
+            let (i, value, bucket) = pool.pick("api.github.com", &limit).unwrap();
+            assert_eq!(i, 1);
+            assert_eq!(value, "token ghp_bbbbbbbb");
+            assert_eq!(bucket, format!("api.github.com ({})", pool.tokens[1].id));
+        }
+    }
+
+    #[test]
+    fn rejected_token_is_set_aside() {
+        let pool = pool(&["ghp_aaaaaaaa", "ghp_bbbbbbbb"]);
+        let limit = RateLimit::default();
+        pool.quarantine(0);
+        let until = pool.tokens[0].quarantined_until.lock().unwrap().unwrap();
+        assert!((until as i64 - (now() + TOKEN_QUARANTINE) as i64).abs() <= 1);
+        assert_eq!(TOKEN_QUARANTINE, 1800);
+        for _ in 0..3 {
+            assert_eq!(pool.pick("api.github.com", &limit).unwrap().0, 1);
+        }
+
+        // the soonest released token is used when all are set aside
+        pool.quarantine(1);
+        *pool.tokens[1].quarantined_until.lock().unwrap() = Some(until + 60);
+        assert_eq!(pool.pick("api.github.com", &limit).unwrap().0, 0);
+
+        pool.restore(0);
+        pool.restore(1);
+        assert!(pool.tokens[1].quarantined_until.lock().unwrap().is_none());
+    }
+}
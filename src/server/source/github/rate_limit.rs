@@ -0,0 +1,244 @@
+use anyhow::{anyhow, Result};
+use chrono::{Local, TimeZone};
+use log::{debug, info, warn};
+use reqwest::header::{self, HeaderMap};
+use reqwest::{Response, StatusCode};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::{self, Duration};
+
+/// The request quota GitHub reported most recently for each host, shared by
+/// every puller.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimit {
+    budgets: Arc<Mutex<HashMap<String, Budget>>>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Budget {
+    pub limit: Option<u64>,
+    pub remaining: Option<u64>,
+    // unix time in seconds
+    pub reset: Option<u64>,
+    pub paused_until: Option<u64>,
+}
+
+enum Wait {
+    Go,
+    Spread(u64),
+    Pause(u64),
+}
+
+impl Budget {
+    fn observe(&mut self, limit: Option<u64>, remaining: u64, reset: u64) {
+        // responses of concurrent requests arrive in any order, keep the
+        // lowest quota seen within a window
+        if self.reset != Some(reset) || !matches!(self.remaining, Some(v) if v <= remaining) {
+            self.remaining = Some(remaining);
+        }
+        self.reset = Some(reset);
+        self.limit = limit.or(self.limit);
+    }
+
+    fn wait(&mut self, now: u64) -> Wait {
+        if let Some(until) = self.paused_until {
+            if until > now {
+                return Wait::Pause(until - now);
+            }
+            self.paused_until = None;
+        }
+        let (Some(remaining), Some(reset)) = (self.remaining, self.reset) else {
+            return Wait::Go;
+        };
+        if reset <= now {
+            // a new window has started, the next response tells its quota
+            self.remaining = None;
+            self.reset = None;
+            return Wait::Go;
+        }
+        if remaining <= RATE_LIMIT_RESERVE {
+            return Wait::Pause(reset - now);
+        }
+        // count the request in flight so that concurrent pullers do not all
+        // spend the same last few requests
+        self.remaining = Some(remaining - 1);
+        let limit = self.limit.unwrap_or(remaining);
+        if remaining * 100 < limit * RATE_LIMIT_LOW_PERCENT {
+            return Wait::Spread((reset - now) / remaining);
+        }
+        Wait::Go
+    }
+}
+
+impl RateLimit {
+    /// Wait until a request can be sent to `host` without exhausting its
+    /// quota. Requests are spread over the rest of the window once the quota
+    /// runs low, and held back until the reset time once it is used up.
+    pub async fn acquire(&self, host: &str) {
+        loop {
+            let wait = self
+                .budgets
+                .lock()
+                .unwrap()
+                .entry(host.to_string())
+                .or_default()
+                .wait(now());
+            match wait {
+                Wait::Go => return,
+                Wait::Spread(secs) => {
+                    debug!(
+                        "GitHub quota of {} is running low. Delay the request {} seconds",
+                        host, secs
+                    );
+                    time::sleep(Duration::from_secs(secs)).await;
+                    return;
+                }
+                Wait::Pause(secs) => {
+                    warn!(
+                        "GitHub quota of {} is used up. Pause requests until {}",
+                        host,
+                        format_time(now() + secs)
+                    );
+                    time::sleep(Duration::from_secs(secs + 1)).await;
+                }
+            }
+        }
+    }
+
+    /// Record the quota reported by a response from `host`. Fails when the
+    /// response says the request was rejected for exceeding the rate limit.
+    pub fn update(&self, host: &str, resp: &Response) -> Result<()> {
+        let headers = resp.headers();
+        let mut budgets = self.budgets.lock().unwrap();
+        let budget = budgets.entry(host.to_string()).or_default();
+        let limit = header_u64(headers, "x-ratelimit-limit");
+        let remaining = header_u64(headers, "x-ratelimit-remaining");
+        let reset = header_u64(headers, "x-ratelimit-reset");
+        if let (Some(remaining), Some(reset)) = (remaining, reset) {
+            budget.observe(limit, remaining, reset);
+        }
+
+        let status = resp.status();
+        if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+            return Ok(());
+        }
+        let until = match (header_u64(headers, header::RETRY_AFTER.as_str()), remaining) {
+            (Some(secs), _) => now() + secs,
+            (None, Some(0)) => reset.unwrap_or_else(|| now() + RATE_LIMIT_DEFAULT_PAUSE),
+            // a secondary rate limit without any hint, back off a while
+            (None, _) if status == StatusCode::TOO_MANY_REQUESTS => {
+                now() + RATE_LIMIT_DEFAULT_PAUSE
+            }
+            // a plain permission error
+            _ => return Ok(()),
+        };
+        budget.paused_until = Some(budget.paused_until.map_or(until, |v| v.max(until)));
+        Err(anyhow!(
+            "rate limited by {} (code = {}). Requests are paused until {}",
+            host,
+            status.as_u16(),
+            format_time(until)
+        ))
+    }
+
+    /// Record the quota a GraphQL response reports in its `rateLimit` field,
+    /// which counts query points rather than requests.
+    pub fn record(&self, bucket: &str, limit: Option<u64>, remaining: u64, reset: u64) {
+        self.budgets
+            .lock()
+            .unwrap()
+            .entry(bucket.to_string())
+            .or_default()
+            .observe(limit, remaining, reset);
+    }
+
+    /// The requests `bucket` can still send in the current window, as far as
+    /// known. None are left while it is paused.
+    pub fn available(&self, bucket: &str) -> u64 {
+        let budget = self.budget(bucket);
+        let now = now();
+        if budget.paused_until.is_some_and(|v| v > now) {
+            return 0;
+        }
+        match (budget.remaining, budget.reset) {
+            (Some(remaining), Some(reset)) if reset > now => remaining,
+            // a fresh window, or a token not used yet
+            _ => u64::MAX,
+        }
+    }
+
+    pub fn budget(&self, host: &str) -> Budget {
+        self.budgets
+            .lock()
+            .unwrap()
+            .get(host)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Log the quota left in the current window of every host.
+    pub fn log_budget(&self) {
+        let budgets = self.budgets.lock().unwrap();
+        for (host, budget) in budgets.iter() {
+            match (budget.remaining, budget.reset) {
+                (Some(remaining), Some(reset)) => info!(
+                    "GitHub rate limit of {}: {}/{} requests left, resets at {}",
+                    host,
+                    remaining,
+                    budget.limit.map_or("?".to_string(), |v| v.to_string()),
+                    format_time(reset)
+                ),
+                _ => debug!("GitHub rate limit of {} is unknown yet.", host),
+            }
+        }
+    }
+}
+
+fn header_u64(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|v| v.as_secs())
+        .unwrap_or(0)
+}
+
+fn format_time(secs: u64) -> String {
+    match Local.timestamp_opt(secs as i64, 0).single() {
+        Some(v) => v.format("%Y-%m-%d %H:%M:%S").to_string(),
+        None => secs.to_string(),
+    }
+}
+
+// requests kept back for the operator when the quota is nearly used up
+const RATE_LIMIT_RESERVE: u64 = 5;
+// below this share of the quota requests are spread over the window
+const RATE_LIMIT_LOW_PERCENT: u64 = 10;
+const RATE_LIMIT_DEFAULT_PAUSE: u64 = 60;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lowest_quota_of_a_window_is_kept() {
+        let limit = RateLimit::default();
+        let reset = now() + 600;
+        limit.record("github.com graphql", Some(5000), 4200, reset);
+        limit.record("github.com graphql", Some(5000), 4300, reset);
+        let budget = limit.budget("github.com graphql");
+        assert_eq!((budget.limit, budget.remaining), (Some(5000), Some(4200)));
+        assert_eq!(limit.available("github.com graphql"), 4200);
+        // other buckets are not touched
+        assert_eq!(limit.available("github.com"), u64::MAX);
+
+        // a new window starts over
+        limit.record("github.com graphql", None, 4990, reset + 3600);
+        let budget = limit.budget("github.com graphql");
+        assert_eq!((budget.limit, budget.remaining), (Some(5000), Some(4990)));
+        assert_eq!(budget.reset, Some(reset + 3600));
+    }
+}
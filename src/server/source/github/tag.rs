@@ -0,0 +1,249 @@
+use super::{get, repo_url, RepoPath};
+use crate::config::Repo;
+use crate::db::{Release, ReleaseDetail};
+use crate::server::source::{paginate, Context as SourceContext, ReleaseSource};
+use crate::server::version;
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use log::trace;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Watches the git tags of a GitHub repo, for projects that never publish
+/// GitHub releases. A release is synthesized from each tag and the date of
+/// the commit it points to.
+pub struct TagSource {
+    name: String,
+    url: String,
+    path: RepoPath,
+    ctx: SourceContext,
+    // commit dates never change, remember them across polls
+    dates: Mutex<HashMap<String, String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Tag {
+    name: String,
+    commit: TagCommit,
+}
+
+#[derive(Debug, Deserialize)]
+struct TagCommit {
+    sha: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Commit {
+    commit: CommitDetail,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommitDetail {
+    committer: CommitSignature,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommitSignature {
+    date: String,
+}
+
+impl TagSource {
+    pub fn new(repo: &Repo, ctx: SourceContext) -> Result<TagSource> {
+        let url = repo_url(repo)?;
+        let path = RepoPath::from_url(&url).ok_or_else(|| {
+            anyhow!(
+                "repo {} url \"{}\" is not a GitHub API repo url",
+                repo.name,
+                url
+            )
+        })?;
+        Ok(TagSource {
+            name: repo.name.clone(),
+            url,
+            path,
+            ctx,
+            dates: Default::default(),
+        })
+    }
+
+    fn list_url(&self) -> String {
+        format!(
+            "{}/repos/{}/{}/tags?per_page={}",
+            self.path.api, self.path.owner, self.path.repo, TAG_PER_PAGE
+        )
+    }
+
+    async fn commit_date(&self, sha: &str) -> Result<String> {
+        if let Some(v) = self.dates.lock().unwrap().get(sha) {
+            return Ok(v.clone());
+        }
+        let url = format!(
+            "{}/repos/{}/{}/commits/{}",
+            self.path.api, self.path.owner, self.path.repo, sha
+        );
+        let commit: Commit = get(&self.ctx, &self.name, &url, false)
+            .await?
+            .json()
+            .await
+            .context("Deserialize http response failed!")?;
+        let date = commit.commit.committer.date;
+        self.dates
+            .lock()
+            .unwrap()
+            .insert(sha.to_string(), date.clone());
+        Ok(date)
+    }
+}
+
+#[async_trait]
+impl ReleaseSource for TagSource {
+    async fn latest(&self) -> Result<Release> {
+        self.releases(None)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("repo {} has no tag", self.name))
+    }
+
+    async fn releases(&self, _since: Option<&str>) -> Result<Vec<Release>> {
+        if let Some(releases) = self.ctx.github_prefetch.take(&self.name) {
+            trace!(
+                "Use the {} tags of the {} fetched by GraphQL.",
+                releases.len(),
+                self.name
+            );
+            return Ok(releases);
+        }
+
+        let what = format!("tags of the {}", self.name);
+        let pages: Vec<Vec<Tag>> = paginate(
+            &what,
+            self.list_url(),
+            TAG_MAX_PAGES,
+            |url, conditional| async move { get(&self.ctx, &self.name, &url, conditional).await },
+            |_| true,
+        )
+        .await?;
+        let mut tags: Vec<Tag> = pages.into_iter().flatten().collect();
+        newest_tags(&mut tags, |v| &v.name);
+
+        let mut releases = Vec::new();
+        for v in tags.iter() {
+            let date = self.commit_date(&v.commit.sha).await?;
+            let detail = tag_detail(&self.path, &v.name, date);
+            releases.push(Release::new(self.url.clone(), self.name.clone(), detail));
+        }
+        Ok(releases)
+    }
+}
+
+/// Keep only the `TAG_DATED` newest tags, which are the ones that get their
+/// commit date looked up. The api lists tags by name, so the highest
+/// versions are put first when there are any.
+pub(super) fn newest_tags<T>(tags: &mut Vec<T>, name: impl Fn(&T) -> &str) {
+    if tags.iter().any(|v| version::parse(name(v)).is_some()) {
+        tags.retain(|v| version::parse(name(v)).is_some());
+        tags.sort_by_cached_key(|v| std::cmp::Reverse(version::parse(name(v))));
+    }
+    tags.truncate(TAG_DATED);
+}
+
+/// Build the release shape the rest of the pipeline expects from a tag and
+/// the date of its commit.
+pub(super) fn tag_detail(path: &RepoPath, tag: &str, date: String) -> ReleaseDetail {
+    let prerelease = version::parse(tag).is_some_and(|v| !v.pre.is_empty());
+    ReleaseDetail {
+        release_name: tag.to_string(),
+        tag_name: tag.to_string(),
+        prerelease,
+        published_at: date,
+        html_url: format!("{}/releases/tag/{}", path.html_url(), tag),
+    }
+}
+
+const TAG_PER_PAGE: u8 = 100;
+const TAG_MAX_PAGES: u8 = 3;
+// how many of the newest tags are turned into releases each poll
+const TAG_DATED: usize = 10;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::source::stub::{self, Reply, Stub};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn tags_are_dated_by_their_commit_highest_version_first() {
+        let github = Stub::start();
+        github.route(
+            "/repos/owner/repo/tags",
+            Reply::json(
+                &json!([
+                    { "name": "nightly", "commit": { "sha": "n" } },
+                    { "name": "v1.10.0", "commit": { "sha": "c" } },
+                    { "name": "v1.2.0", "commit": { "sha": "a" } },
+                    { "name": "v1.9.0-rc.1", "commit": { "sha": "b" } },
+                ])
+                .to_string(),
+            ),
+        );
+        for (sha, date) in [
+            ("a", "2024-01-01"),
+            ("b", "2024-02-01"),
+            ("c", "2024-03-01"),
+        ] {
+            let commit =
+                json!({ "commit": { "committer": { "date": format!("{}T00:00:00Z", date) } } });
+            github.route(
+                &format!("/repos/owner/repo/commits/{}", sha),
+                Reply::json(&commit.to_string()),
+            );
+        }
+        let repo: Repo = serde_json::from_value(json!({
+            "name": "repo",
+            "type": "github-tag",
+            "url": format!("{}/repos/owner/repo/releases/latest", github.url()),
+        }))
+        .unwrap();
+        let source = TagSource::new(&repo, stub::context()).unwrap();
+
+        let releases = source.releases(None).await.unwrap();
+        let tags: Vec<&str> = releases
+            .iter()
+            .map(|v| v.detail.tag_name.as_str())
+            .collect();
+        assert_eq!(tags, ["v1.10.0", "v1.9.0-rc.1", "v1.2.0"]);
+        let detail = &releases[1].detail;
+        assert_eq!(detail.release_name, "v1.9.0-rc.1");
+        assert!(detail.prerelease);
+        assert_eq!(detail.published_at, "2024-02-01T00:00:00Z");
+        assert_eq!(
+            detail.html_url,
+            format!("{}/owner/repo/releases/tag/v1.9.0-rc.1", github.url())
+        );
+        assert!(!releases[0].detail.prerelease);
+
+        // the commit dates are only looked up once
+        source.releases(None).await.unwrap();
+        let commits = github
+            .requests()
+            .into_iter()
+            .filter(|v| v.contains("/commits/"))
+            .count();
+        assert_eq!(commits, 3);
+    }
+
+    #[test]
+    fn only_the_newest_versions_are_kept() {
+        let mut tags: Vec<String> = (0..15).map(|v| format!("v1.{}.0", v)).collect();
+        tags.push("latest".to_string());
+        newest_tags(&mut tags, |v| v);
+        assert_eq!(tags.len(), TAG_DATED);
+        assert_eq!(tags[0], "v1.14.0");
+
+        let mut tags = vec!["nightly".to_string(), "stable".to_string()];
+        newest_tags(&mut tags, |v| v);
+        assert_eq!(tags, ["nightly", "stable"]);
+    }
+}
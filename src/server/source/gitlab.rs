@@ -0,0 +1,319 @@
+use crate::config::Repo;
+use crate::db::{Release, ReleaseDetail};
+use crate::server::version;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use log::trace;
+use reqwest::Response;
+use serde::Deserialize;
+
+/// Watches the releases, or the tags, of a project on gitlab.com or a
+/// self-hosted GitLab instance through the v4 REST API.
+pub struct Source {
+    name: String,
+    // the instance, e.g. https://gitlab.com
+    base: String,
+    // a project path like `group/project` or a numeric id
+    project: String,
+    token: Option<String>,
+    tags: bool,
+    ctx: super::Context,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitlabRelease {
+    name: Option<String>,
+    tag_name: String,
+    released_at: Option<String>,
+    #[serde(default)]
+    upcoming_release: bool,
+    #[serde(rename = "_links")]
+    links: Option<GitlabLinks>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitlabLinks {
+    #[serde(rename = "self")]
+    self_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitlabTag {
+    name: String,
+    commit: GitlabCommit,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitlabCommit {
+    committed_date: String,
+}
+
+impl Source {
+    pub fn new(repo: &Repo, ctx: super::Context, tags: bool) -> Result<Source> {
+        let project = repo
+            .project
+            .clone()
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| anyhow!("repo {} needs a gitlab project path or id", repo.name))?;
+        Ok(Source {
+            name: repo.name.clone(),
+            base: super::base_url(repo, GITLAB_URL),
+            project,
+            token: repo.token.clone(),
+            tags,
+            ctx,
+        })
+    }
+
+    fn api_url(&self, path: &str) -> String {
+        format!(
+            "{}/api/v4/projects/{}/{}",
+            self.base,
+            super::encode_component(&self.project),
+            path
+        )
+    }
+
+    /// The web page of the project, numeric ids are redirected by GitLab.
+    fn web_url(&self) -> String {
+        if self.project.chars().all(|c| c.is_ascii_digit()) {
+            format!("{}/projects/{}", self.base, self.project)
+        } else {
+            format!("{}/{}", self.base, self.project)
+        }
+    }
+
+    async fn get(&self, url: &str, conditional: bool) -> Result<Response> {
+        let mut request = self.ctx.client.get(url);
+        if let Some(token) = &self.token {
+            request = request.header(GITLAB_TOKEN_HEADER, token);
+        }
+        let resp = if conditional {
+            self.ctx.conditional_send(&self.name, url, request).await?
+        } else {
+            request.send().await?
+        };
+        Ok(resp.error_for_status()?)
+    }
+
+    /// Fetch a listing endpoint for up to `GITLAB_MAX_PAGES` pages while
+    /// `more` says older items are wanted.
+    async fn list<T, F>(&self, what: &str, url: String, mut more: F) -> Result<Vec<T>>
+    where
+        T: for<'de> Deserialize<'de>,
+        F: FnMut(&[T]) -> bool,
+    {
+        let what = format!("{} of the {}", what, self.name);
+        let pages: Vec<Vec<T>> = super::paginate(
+            &what,
+            url,
+            GITLAB_MAX_PAGES,
+            |url, conditional| async move { self.get(&url, conditional).await },
+            |page: &Vec<T>| more(page),
+        )
+        .await?;
+        Ok(pages.into_iter().flatten().collect())
+    }
+
+    async fn fetch_releases(&self, since: Option<&str>) -> Result<Vec<Release>> {
+        let url = self.api_url(&format!("releases?per_page={}", GITLAB_PER_PAGE));
+        let items: Vec<GitlabRelease> = self
+            .list("releases", url, |batch: &[GitlabRelease]| {
+                !batch.iter().any(|v| {
+                    since.is_some_and(|s| v.released_at.as_deref().is_some_and(|d| d <= s))
+                })
+            })
+            .await?;
+
+        let releases = items
+            .into_iter()
+            // a release scheduled for the future is not out yet
+            .filter(|v| !v.upcoming_release)
+            .filter_map(|v| {
+                let html_url = v
+                    .links
+                    .and_then(|v| v.self_url)
+                    .unwrap_or_else(|| format!("{}/-/releases/{}", self.web_url(), v.tag_name));
+                let detail = ReleaseDetail {
+                    release_name: v.name.unwrap_or_else(|| v.tag_name.clone()),
+                    prerelease: is_prerelease(&v.tag_name),
+                    published_at: v.released_at?,
+                    html_url,
+                    tag_name: v.tag_name,
+                };
+                Some(Release::new(self.base.clone(), self.name.clone(), detail))
+            })
+            .collect();
+        Ok(releases)
+    }
+
+    async fn fetch_tags(&self) -> Result<Vec<Release>> {
+        let url = self.api_url(&format!(
+            "repository/tags?order_by=version&per_page={}",
+            GITLAB_PER_PAGE
+        ));
+        // ordered by version, the newest tags are all on the first page
+        let items: Vec<GitlabTag> = self.list("tags", url, |_| false).await?;
+
+        let releases = items
+            .into_iter()
+            .map(|v| {
+                let detail = ReleaseDetail {
+                    release_name: v.name.clone(),
+                    prerelease: is_prerelease(&v.name),
+                    published_at: v.commit.committed_date,
+                    html_url: format!("{}/-/tags/{}", self.web_url(), v.name),
+                    tag_name: v.name,
+                };
+                Release::new(self.base.clone(), self.name.clone(), detail)
+            })
+            .collect();
+        Ok(releases)
+    }
+}
+
+#[async_trait]
+impl super::ReleaseSource for Source {
+    async fn latest(&self) -> Result<Release> {
+        let mut releases = self.releases(None).await?;
+        releases.sort_by(|a, b| a.detail.published_at.cmp(&b.detail.published_at));
+        releases
+            .pop()
+            .ok_or_else(|| anyhow!("project {} has no release", self.project))
+    }
+
+    async fn releases(&self, since: Option<&str>) -> Result<Vec<Release>> {
+        let releases = if self.tags {
+            self.fetch_tags().await?
+        } else {
+            self.fetch_releases(since).await?
+        };
+        trace!(
+            "Collected {} releases of the {} from GitLab.",
+            releases.len(),
+            self.name
+        );
+        Ok(releases)
+    }
+}
+
+// gitlab has no pre-release flag, go by the version in the tag
+fn is_prerelease(tag: &str) -> bool {
+    version::parse(tag).is_some_and(|v| !v.pre.is_empty())
+}
+
+const GITLAB_URL: &str = "https://gitlab.com";
+const GITLAB_TOKEN_HEADER: &str = "PRIVATE-TOKEN";
+const GITLAB_PER_PAGE: u8 = 30;
+const GITLAB_MAX_PAGES: u8 = 5;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::source::stub::{self, Reply, Stub};
+    use crate::server::source::ReleaseSource;
+    use serde_json::json;
+
+    const PROJECT: &str = "/api/v4/projects/group%2Fsubgroup%2Fproject";
+
+    fn source(gitlab: &Stub, tags: bool) -> Source {
+        let repo: Repo = serde_json::from_value(json!({
+            "name": "project",
+            "type": if tags { "gitlab-tag" } else { "gitlab" },
+            "url": format!("{}/", gitlab.url()),
+            "project": "group/subgroup/project",
+            "token": "glpat-secret",
+        }))
+        .unwrap();
+        Source::new(&repo, stub::context(), tags).unwrap()
+    }
+
+    #[tokio::test]
+    async fn releases_of_a_nested_project() {
+        let gitlab = Stub::start();
+        let releases = json!([
+            {
+                "name": null,
+                "tag_name": "v2.0.0",
+                "released_at": "2030-01-01T00:00:00Z",
+                "upcoming_release": true,
+            },
+            {
+                "name": "Second",
+                "tag_name": "v1.1.0-rc.1",
+                "released_at": "2024-02-01T00:00:00Z",
+                "_links": { "self": "https://gitlab.example/group/subgroup/project/-/releases/v1.1.0-rc.1" },
+            },
+            {
+                "name": null,
+                "tag_name": "v1.0.0",
+                "released_at": "2024-01-01T00:00:00Z",
+            },
+        ]);
+        gitlab.route(
+            &format!("{}/releases", PROJECT),
+            Reply::json(&releases.to_string()),
+        );
+
+        let releases = source(&gitlab, false).releases(None).await.unwrap();
+        let tags: Vec<&str> = releases
+            .iter()
+            .map(|v| v.detail.tag_name.as_str())
+            .collect();
+        assert_eq!(tags, ["v1.1.0-rc.1", "v1.0.0"]);
+        assert!(releases[0].detail.prerelease);
+        assert_eq!(
+            releases[0].detail.html_url,
+            "https://gitlab.example/group/subgroup/project/-/releases/v1.1.0-rc.1"
+        );
+        assert_eq!(releases[1].detail.release_name, "v1.0.0");
+        assert_eq!(
+            releases[1].detail.html_url,
+            format!("{}/group/subgroup/project/-/releases/v1.0.0", gitlab.url())
+        );
+
+        let received = gitlab.received();
+        assert_eq!(
+            received[0].target,
+            format!("{}/releases?per_page={}", PROJECT, GITLAB_PER_PAGE)
+        );
+        assert_eq!(
+            received[0].header(GITLAB_TOKEN_HEADER),
+            Some("glpat-secret")
+        );
+    }
+
+    #[tokio::test]
+    async fn tags_of_a_nested_project() {
+        let gitlab = Stub::start();
+        let tags = json!([
+            { "name": "v1.1.0", "commit": { "committed_date": "2024-02-01T00:00:00.000+01:00" } },
+            { "name": "v1.0.0", "commit": { "committed_date": "2024-01-01T00:00:00.000+01:00" } },
+        ]);
+        gitlab.route(
+            &format!("{}/repository/tags", PROJECT),
+            Reply::json(&tags.to_string()),
+        );
+
+        let latest = source(&gitlab, true).latest().await.unwrap();
+        assert_eq!(latest.detail.tag_name, "v1.1.0");
+        assert_eq!(latest.detail.published_at, "2024-02-01T00:00:00.000+01:00");
+        assert_eq!(
+            latest.detail.html_url,
+            format!("{}/group/subgroup/project/-/tags/v1.1.0", gitlab.url())
+        );
+
+        let received = gitlab.received();
+        assert_eq!(
+            received[0].target,
+            format!(
+                "{}/repository/tags?order_by=version&per_page={}",
+                PROJECT, GITLAB_PER_PAGE
+            )
+        );
+        assert_eq!(
+            received[0].header(GITLAB_TOKEN_HEADER),
+            Some("glpat-secret")
+        );
+    }
+}
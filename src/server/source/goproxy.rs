@@ -0,0 +1,152 @@
+use crate::config::Repo;
+use crate::db::{Release, ReleaseDetail};
+use crate::server::version;
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use log::{debug, trace};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Watches the versions of a Go module through a module proxy such as
+/// proxy.golang.org, or an internal one speaking the GOPROXY protocol.
+pub struct Source {
+    name: String,
+    // the proxy, e.g. https://proxy.golang.org
+    base: String,
+    module: String,
+    ctx: super::Context,
+    // the time of a version never changes, remember it across polls
+    times: Mutex<HashMap<String, String>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Info {
+    version: String,
+    time: String,
+}
+
+impl Source {
+    pub fn new(repo: &Repo, ctx: super::Context) -> Result<Source> {
+        let module = repo
+            .package
+            .clone()
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| anyhow!("repo {} needs the path of a go module", repo.name))?;
+        Ok(Source {
+            name: repo.name.clone(),
+            base: super::base_url(repo, GOPROXY_URL),
+            module,
+            ctx,
+            times: Default::default(),
+        })
+    }
+
+    fn module_url(&self, path: &str) -> String {
+        format!("{}/{}/@{}", self.base, escape_path(&self.module), path)
+    }
+
+    async fn info(&self, path: &str) -> Result<Info> {
+        let info = self
+            .ctx
+            .client
+            .get(self.module_url(path))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("Deserialize http response failed!")?;
+        Ok(info)
+    }
+
+    async fn time(&self, num: &str) -> Result<String> {
+        if let Some(v) = self.times.lock().unwrap().get(num) {
+            return Ok(v.clone());
+        }
+        let info = self.info(&format!("v/{}.info", escape_path(num))).await?;
+        self.times
+            .lock()
+            .unwrap()
+            .insert(num.to_string(), info.time.clone());
+        Ok(info.time)
+    }
+
+    fn release(&self, num: String, published_at: String) -> Release {
+        let detail = ReleaseDetail {
+            release_name: format!("{} {}", self.module, num),
+            prerelease: version::parse(&num).is_some_and(|v| !v.pre.is_empty()),
+            published_at,
+            html_url: format!("{}/{}@{}", GO_PKG_URL, self.module, num),
+            tag_name: num,
+        };
+        Release::new(self.base.clone(), self.name.clone(), detail)
+    }
+}
+
+#[async_trait]
+impl super::ReleaseSource for Source {
+    async fn latest(&self) -> Result<Release> {
+        let info = self.info("latest").await?;
+        debug!("Requested the latest version of the module {}", self.module);
+        Ok(self.release(info.version, info.time))
+    }
+
+    async fn releases(&self, _since: Option<&str>) -> Result<Vec<Release>> {
+        let url = self.module_url("v/list");
+        let request = self.ctx.client.get(&url);
+        let body = self
+            .ctx
+            .conditional_send(&self.name, &url, request)
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        debug!("Requested the versions of the module {}", self.module);
+
+        // the list has the tagged versions in no particular order
+        let mut nums: Vec<&str> = body
+            .lines()
+            .map(str::trim)
+            .filter(|v| version::parse(v).is_some())
+            .collect();
+        if nums.is_empty() {
+            // a module without any tag only has pseudo-versions
+            return Ok(vec![self.latest().await?]);
+        }
+        nums.sort_by_cached_key(|v| std::cmp::Reverse(version::parse(v)));
+
+        let mut releases = Vec::new();
+        for num in nums.into_iter().take(GOPROXY_DATED) {
+            let published_at = self.time(num).await?;
+            releases.push(self.release(num.to_string(), published_at));
+        }
+        trace!(
+            "Collected {} versions of the module {}.",
+            releases.len(),
+            self.module
+        );
+        Ok(releases)
+    }
+}
+
+/// Escape a module path or version the way the proxy protocol wants it:
+/// each upper case letter becomes `!` and its lower case form.
+fn escape_path(path: &str) -> String {
+    let mut escaped = String::with_capacity(path.len());
+    for c in path.chars() {
+        if c.is_ascii_uppercase() {
+            escaped.push('!');
+            escaped.push(c.to_ascii_lowercase());
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+const GOPROXY_URL: &str = "https://proxy.golang.org";
+const GO_PKG_URL: &str = "https://pkg.go.dev";
+// how many of the newest versions are turned into releases each poll
+const GOPROXY_DATED: usize = 10;
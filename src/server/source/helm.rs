@@ -0,0 +1,283 @@
+use super::{oci, ReleaseSource};
+use crate::config::Repo;
+use crate::db::{Release, ReleaseDetail};
+use crate::server::filter::Filter;
+use crate::server::version;
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use log::{debug, trace};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Watches the versions of a Helm chart, published either in a chart
+/// repository's `index.yaml` or as an artifact in an OCI registry.
+pub struct Source {
+    name: String,
+    // the chart repository, e.g. https://charts.bitnami.com/bitnami
+    url: String,
+    chart: String,
+    // set when the chart is pushed to an OCI registry
+    oci: Option<oci::Source>,
+    // the app version of a chart version never changes, remember it across
+    // polls
+    app_versions: Mutex<HashMap<String, Option<String>>>,
+    filter: Filter,
+    ctx: super::Context,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChartIndex {
+    #[serde(default)]
+    entries: HashMap<String, Vec<ChartVersion>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChartVersion {
+    version: String,
+    app_version: Option<String>,
+    created: Option<String>,
+    #[serde(default)]
+    urls: Vec<String>,
+}
+
+impl Source {
+    pub fn new(repo: &Repo, ctx: super::Context) -> Result<Source> {
+        let chart = repo
+            .package
+            .clone()
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| anyhow!("repo {} needs the name of a chart", repo.name))?;
+        let url = repo.url.trim_end_matches('/');
+        if url.is_empty() {
+            return Err(anyhow!(
+                "repo {} needs the url of a chart repository",
+                repo.name
+            ));
+        }
+        let oci = match url.strip_prefix(OCI_SCHEME) {
+            // a chart pushed to `oci://host/path` is the image `host/path/chart`
+            Some(path) => {
+                let mut image = repo.clone();
+                image.url = String::new();
+                image.package = Some(format!("{}/{}", path, chart));
+                image.digest_tag = None;
+                Some(oci::Source::new(&image, ctx.clone())?)
+            }
+            None => None,
+        };
+        Ok(Source {
+            name: repo.name.clone(),
+            url: url.to_string(),
+            chart,
+            oci,
+            app_versions: Default::default(),
+            filter: Filter::from_repo(repo)?,
+            ctx,
+        })
+    }
+
+    fn release(
+        &self,
+        num: &str,
+        app_version: Option<&str>,
+        published_at: String,
+        html_url: String,
+    ) -> Release {
+        let release_name = match app_version {
+            Some(app) => format!("{} {} (app {})", self.chart, num, app),
+            None => format!("{} {}", self.chart, num),
+        };
+        let detail = ReleaseDetail {
+            release_name,
+            tag_name: num.to_string(),
+            prerelease: version::parse(num).is_some_and(|v| !v.pre.is_empty()),
+            published_at,
+            html_url,
+        };
+        let mut release = Release::new(self.url.clone(), self.name.clone(), detail);
+        release.version = version::parse(num).map(|v| v.to_string());
+        release
+    }
+
+    async fn fetch_index(&self) -> Result<Vec<Release>> {
+        let url = self.url.as_str();
+        let index_url = format!("{}/index.yaml", url);
+        let request = self.ctx.client.get(&index_url);
+        let body = self
+            .ctx
+            .conditional_send(&self.name, &index_url, request)
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        let mut index: ChartIndex =
+            serde_yaml::from_slice(&body).context("Deserialize index.yaml failed!")?;
+        debug!("Requested the chart index of the {}", self.name);
+
+        let versions = index
+            .entries
+            .remove(&self.chart)
+            .ok_or_else(|| anyhow!("chart {} is not in the index {}", self.chart, index_url))?;
+        let releases = versions
+            .into_iter()
+            .filter_map(|v| {
+                // the chart archive, which may be given relative to the index
+                let html_url = match v.urls.first() {
+                    Some(u) if u.contains("://") => u.clone(),
+                    Some(u) => format!("{}/{}", url, u),
+                    None => url.to_string(),
+                };
+                let release =
+                    self.release(&v.version, v.app_version.as_deref(), v.created?, html_url);
+                Some(release)
+            })
+            .collect();
+        Ok(releases)
+    }
+
+    async fn fetch_oci(&self, source: &oci::Source) -> Result<Vec<Release>> {
+        let mut releases = Vec::new();
+        for v in source.releases(None).await? {
+            // helm pushes `+` in a chart version as `_`, tags cannot have it
+            let tag = v.detail.tag_name;
+            let num = tag.replace('_', "+");
+            let cached = self.app_versions.lock().unwrap().get(&num).cloned();
+            let app_version = match cached {
+                Some(v) => v,
+                None => {
+                    let config = source.config(&tag).await?;
+                    let app = config["appVersion"].as_str().map(String::from);
+                    self.app_versions
+                        .lock()
+                        .unwrap()
+                        .insert(num.clone(), app.clone());
+                    app
+                }
+            };
+            releases.push(self.release(
+                &num,
+                app_version.as_deref(),
+                v.detail.published_at,
+                v.detail.html_url,
+            ));
+        }
+        Ok(releases)
+    }
+}
+
+#[async_trait]
+impl ReleaseSource for Source {
+    async fn latest(&self) -> Result<Release> {
+        self.releases(None)
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow!("chart {} has no version", self.chart))
+    }
+
+    async fn releases(&self, _since: Option<&str>) -> Result<Vec<Release>> {
+        let releases = match &self.oci {
+            Some(source) => self.fetch_oci(source).await?,
+            None => self.fetch_index().await?,
+        };
+        // an index lists every version ever released, keep the newest of
+        // those matching the filters
+        let mut releases: Vec<Release> = releases
+            .into_iter()
+            .filter(|v| v.version.is_some() && self.filter.matches(v))
+            .collect();
+        releases.sort_by_cached_key(|v| version::parse(&v.detail.tag_name));
+        releases.drain(..releases.len().saturating_sub(super::REGISTRY_VERSION_LIMIT));
+        trace!(
+            "Collected {} versions of the chart {}.",
+            releases.len(),
+            self.chart
+        );
+        Ok(releases)
+    }
+}
+
+const OCI_SCHEME: &str = "oci://";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::source::stub::{self, Reply, Stub};
+    use crate::server::source::NotModified;
+    use serde_json::json;
+
+    const INDEX: &str = r#"apiVersion: v1
+entries:
+  demo:
+    - version: 1.1.0-rc.1
+      created: "2024-02-01T00:00:00Z"
+      urls:
+        - https://charts.example/demo-1.1.0-rc.1.tgz
+    - version: 1.0.0
+      appVersion: "2.4"
+      created: "2024-01-01T00:00:00Z"
+      urls:
+        - charts/demo-1.0.0.tgz
+    - version: 0.9.0
+  other:
+    - version: 9.0.0
+      created: "2024-03-01T00:00:00Z"
+"#;
+
+    #[tokio::test]
+    async fn chart_versions_are_read_from_the_index() {
+        let charts = Stub::start();
+        charts.replies(
+            "/index.yaml",
+            vec![
+                Reply::json(INDEX).header("ETag", "\"i1\""),
+                Reply::status(304),
+            ],
+        );
+        let repo: Repo = serde_json::from_value(json!({
+            "name": "demo",
+            "type": "helm",
+            "url": format!("{}/", charts.url()),
+            "package": "demo",
+        }))
+        .unwrap();
+        let ctx = stub::context();
+        let source = Source::new(&repo, ctx.clone()).unwrap();
+
+        let releases = source.releases(None).await.unwrap();
+        let found: Vec<(&str, Option<&str>, bool)> = releases
+            .iter()
+            .map(|v| {
+                let detail = &v.detail;
+                (
+                    detail.release_name.as_str(),
+                    v.version.as_deref(),
+                    detail.prerelease,
+                )
+            })
+            .collect();
+        // a version without a creation time is left out
+        assert_eq!(
+            found,
+            [
+                ("demo 1.0.0 (app 2.4)", Some("1.0.0"), false),
+                ("demo 1.1.0-rc.1", Some("1.1.0-rc.1"), true),
+            ]
+        );
+        assert_eq!(
+            releases[0].detail.html_url,
+            format!("{}/charts/demo-1.0.0.tgz", charts.url())
+        );
+        assert_eq!(
+            releases[1].detail.html_url,
+            "https://charts.example/demo-1.1.0-rc.1.tgz"
+        );
+
+        // the index is only fetched again when it changed
+        ctx.commit_validators("demo").unwrap();
+        let err = source.releases(None).await.unwrap_err();
+        assert!(err.is::<NotModified>());
+        assert_eq!(charts.received()[1].header("If-None-Match"), Some("\"i1\""));
+    }
+}
@@ -0,0 +1,359 @@
+use crate::config::Repo;
+use crate::db::{Release, ReleaseDetail};
+use crate::server::version;
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use log::{debug, trace};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Where the fields of a release are found in a JSON response, as JSONPath
+/// expressions like `$.data.version` or `$.releases[0].tag`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct JsonPaths {
+    // selects the release objects when the body lists several, the other
+    // paths are then taken relative to each of them
+    #[serde(default)]
+    pub items: Option<String>,
+    pub version: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub date: Option<String>,
+    #[serde(default)]
+    pub link: Option<String>,
+}
+
+/// Reads the release of a service that reports its version as JSON, at a
+/// url and with headers given in the config.
+pub struct Source {
+    name: String,
+    url: String,
+    headers: HeaderMap,
+    items: Option<Path>,
+    version: Path,
+    release_name: Option<Path>,
+    date: Option<Path>,
+    link: Option<Path>,
+    ctx: super::Context,
+}
+
+impl Source {
+    pub fn new(repo: &Repo, ctx: super::Context) -> Result<Source> {
+        let paths = repo
+            .json_path
+            .as_ref()
+            .ok_or_else(|| anyhow!("repo {} needs the jsonPath of its version", repo.name))?;
+        let mut headers = HeaderMap::new();
+        for (k, v) in repo.headers.iter() {
+            headers.insert(
+                HeaderName::from_bytes(k.as_bytes())
+                    .with_context(|| format!("invalid header name \"{}\"", k))?,
+                HeaderValue::from_str(v)
+                    .with_context(|| format!("invalid value of the header \"{}\"", k))?,
+            );
+        }
+        let optional = |v: &Option<String>| v.as_deref().map(Path::parse).transpose();
+        Ok(Source {
+            name: repo.name.clone(),
+            url: repo.url.clone(),
+            headers,
+            items: optional(&paths.items)?,
+            version: Path::parse(&paths.version)?,
+            release_name: optional(&paths.name)?,
+            date: optional(&paths.date)?,
+            link: optional(&paths.link)?,
+            ctx,
+        })
+    }
+
+    fn release(&self, item: &Value, now: &str) -> Option<Release> {
+        let tag = self.version.first(item).and_then(as_text)?;
+        let release_name = self
+            .release_name
+            .as_ref()
+            .and_then(|v| v.first(item))
+            .and_then(as_text)
+            .unwrap_or_else(|| tag.clone());
+        // without a date the release is dated when it is found
+        let published_at = self
+            .date
+            .as_ref()
+            .and_then(|v| v.first(item))
+            .and_then(as_date)
+            .unwrap_or_else(|| now.to_string());
+        let html_url = self
+            .link
+            .as_ref()
+            .and_then(|v| v.first(item))
+            .and_then(as_text)
+            .unwrap_or_else(|| self.url.clone());
+        let detail = ReleaseDetail {
+            release_name,
+            prerelease: version::parse(&tag).is_some_and(|v| !v.pre.is_empty()),
+            tag_name: tag,
+            published_at,
+            html_url,
+        };
+        Some(Release::new(self.url.clone(), self.name.clone(), detail))
+    }
+}
+
+#[async_trait]
+impl super::ReleaseSource for Source {
+    async fn latest(&self) -> Result<Release> {
+        self.releases(None)
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow!("no version found at {}", self.url))
+    }
+
+    async fn releases(&self, _since: Option<&str>) -> Result<Vec<Release>> {
+        let request = self.ctx.client.get(&self.url).headers(self.headers.clone());
+        let body: Value = self
+            .ctx
+            .conditional_send(&self.name, &self.url, request)
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("Deserialize http response failed!")?;
+        debug!("Requested the version endpoint of the {}", self.name);
+
+        let now = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+        let items = match &self.items {
+            Some(v) => v.query(&body),
+            None => vec![&body],
+        };
+        let releases: Vec<Release> = items
+            .into_iter()
+            .filter_map(|v| self.release(v, &now))
+            .collect();
+        trace!(
+            "Extracted {} releases of the {} from the response.",
+            releases.len(),
+            self.name
+        );
+        Ok(releases)
+    }
+}
+
+/// A JSONPath expression, limited to the selectors a version endpoint
+/// needs: `.name`, `['name']`, `[index]` and the `*` wildcard.
+#[derive(Debug, Clone)]
+struct Path(Vec<Selector>);
+
+#[derive(Debug, Clone)]
+enum Selector {
+    Key(String),
+    Index(i64),
+    Wildcard,
+}
+
+impl Path {
+    fn parse(expr: &str) -> Result<Path> {
+        let invalid = || anyhow!("invalid JSONPath \"{}\"", expr);
+        let mut rest = expr.trim().strip_prefix('$').ok_or_else(invalid)?;
+        let mut selectors = Vec::new();
+        while !rest.is_empty() {
+            if let Some(v) = rest.strip_prefix('.') {
+                let end = v.find(['.', '[']).unwrap_or(v.len());
+                let key = &v[..end];
+                selectors.push(match key {
+                    // recursive descent is not supported
+                    "" => return Err(invalid()),
+                    "*" => Selector::Wildcard,
+                    _ => Selector::Key(key.to_string()),
+                });
+                rest = &v[end..];
+            } else if let Some(v) = rest.strip_prefix('[') {
+                let end = v.find(']').ok_or_else(invalid)?;
+                let inner = v[..end].trim();
+                let quoted = inner
+                    .strip_prefix('\'')
+                    .and_then(|v| v.strip_suffix('\''))
+                    .or_else(|| inner.strip_prefix('"').and_then(|v| v.strip_suffix('"')));
+                selectors.push(match quoted {
+                    Some(key) => Selector::Key(key.to_string()),
+                    None if inner == "*" => Selector::Wildcard,
+                    None => Selector::Index(inner.parse().map_err(|_| invalid())?),
+                });
+                rest = &v[end + 1..];
+            } else {
+                return Err(invalid());
+            }
+        }
+        Ok(Path(selectors))
+    }
+
+    fn query<'a>(&self, value: &'a Value) -> Vec<&'a Value> {
+        let mut nodes = vec![value];
+        for selector in self.0.iter() {
+            nodes = nodes
+                .into_iter()
+                .flat_map(|v| -> Vec<&'a Value> {
+                    match (selector, v) {
+                        (Selector::Key(key), Value::Object(map)) => {
+                            map.get(key).into_iter().collect()
+                        }
+                        (Selector::Index(i), Value::Array(list)) => {
+                            // a negative index counts from the end
+                            let i = if *i < 0 { list.len() as i64 + i } else { *i };
+                            usize::try_from(i)
+                                .ok()
+                                .and_then(|i| list.get(i))
+                                .into_iter()
+                                .collect()
+                        }
+                        (Selector::Wildcard, Value::Array(list)) => list.iter().collect(),
+                        (Selector::Wildcard, Value::Object(map)) => map.values().collect(),
+                        _ => Vec::new(),
+                    }
+                })
+                .collect();
+        }
+        nodes
+    }
+
+    fn first<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        self.query(value).into_iter().next()
+    }
+}
+
+fn as_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(v) => Some(v.trim().to_string()).filter(|v| !v.is_empty()),
+        Value::Number(v) => Some(v.to_string()),
+        _ => None,
+    }
+}
+
+// dates are compared as text, so bring them to the form the other sources use
+fn as_date(value: &Value) -> Option<String> {
+    let date = match value {
+        Value::String(v) => match DateTime::parse_from_rfc3339(v.trim()) {
+            Ok(v) => v.with_timezone(&Utc),
+            Err(_) => return Some(v.clone()),
+        },
+        // unix time, in seconds or milliseconds
+        Value::Number(v) => {
+            let v = v.as_i64()?;
+            if v > 100_000_000_000 {
+                Utc.timestamp_millis_opt(v).single()?
+            } else {
+                Utc.timestamp_opt(v, 0).single()?
+            }
+        }
+        _ => return None,
+    };
+    Some(date.to_rfc3339_opts(SecondsFormat::Secs, true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn query(expr: &str, value: &Value) -> Vec<Value> {
+        Path::parse(expr)
+            .unwrap()
+            .query(value)
+            .into_iter()
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn paths_select_keys_and_indexes() {
+        let body = json!({
+            "data": { "version": "1.2.3", "build info": { "date": 1700000000 } },
+            "releases": [{ "tag": "v1.0" }, { "tag": "v1.1" }, { "tag": "v2.0" }],
+        });
+        assert_eq!(query("$", &body), vec![body.clone()]);
+        assert_eq!(query("$.data.version", &body), [json!("1.2.3")]);
+        assert_eq!(query(" $.data.version ", &body), [json!("1.2.3")]);
+        assert_eq!(query("$['data'][\"version\"]", &body), [json!("1.2.3")]);
+        assert_eq!(
+            query("$.data['build info'].date", &body),
+            [json!(1700000000)]
+        );
+        assert_eq!(query("$.releases[0].tag", &body), [json!("v1.0")]);
+        assert_eq!(query("$.releases[ 1 ].tag", &body), [json!("v1.1")]);
+        // a negative index counts from the end
+        assert_eq!(query("$.releases[-1].tag", &body), [json!("v2.0")]);
+        assert_eq!(query("$.releases[-3].tag", &body), [json!("v1.0")]);
+    }
+
+    #[test]
+    fn paths_select_nothing_when_missing() {
+        let body = json!({ "releases": [{ "tag": "v1.0" }], "version": "1.0" });
+        assert!(query("$.missing", &body).is_empty());
+        assert!(query("$.releases[1]", &body).is_empty());
+        assert!(query("$.releases[-2]", &body).is_empty());
+        assert!(query("$.version.major", &body).is_empty());
+        assert!(query("$.version[0]", &body).is_empty());
+        assert!(query("$.releases.tag", &body).is_empty());
+    }
+
+    #[test]
+    fn wildcards_select_every_child() {
+        let body = json!({
+            "releases": [{ "tag": "v1.0" }, { "name": "no tag" }, { "tag": "v1.1" }],
+            "channels": { "stable": { "version": "1.0" }, "beta": { "version": "1.1-beta" } },
+        });
+        assert_eq!(
+            query("$.releases[*].tag", &body),
+            [json!("v1.0"), json!("v1.1")]
+        );
+        assert_eq!(
+            query("$.releases.*.tag", &body),
+            [json!("v1.0"), json!("v1.1")]
+        );
+        let mut versions = query("$.channels.*.version", &body);
+        versions.sort_by_key(|v| v.to_string());
+        assert_eq!(versions, [json!("1.0"), json!("1.1-beta")]);
+        assert_eq!(query("$.releases[*]", &body).len(), 3);
+        assert!(query("$.releases[0].tag.*", &body).is_empty());
+    }
+
+    #[test]
+    fn invalid_paths_are_refused() {
+        for expr in [
+            "",
+            "data.version",
+            "$..version",
+            "$.data.",
+            "$[0",
+            "$.releases[abc]",
+            "$.releases[1.5]",
+            "$.releases[0]tag",
+            "$ .data",
+        ] {
+            assert!(Path::parse(expr).is_err(), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn dates_are_brought_to_utc() {
+        assert_eq!(
+            as_date(&json!("2024-01-15T10:00:00+02:00")).as_deref(),
+            Some("2024-01-15T08:00:00Z")
+        );
+        assert_eq!(
+            as_date(&json!(1700000000)).as_deref(),
+            Some("2023-11-14T22:13:20Z")
+        );
+        assert_eq!(
+            as_date(&json!(1700000000000_i64)).as_deref(),
+            Some("2023-11-14T22:13:20Z")
+        );
+        // kept as it is when it cannot be read
+        assert_eq!(
+            as_date(&json!("Jan 15, 2024")).as_deref(),
+            Some("Jan 15, 2024")
+        );
+        assert_eq!(as_date(&json!(null)), None);
+    }
+}
@@ -0,0 +1,405 @@
+pub mod crates_io;
+pub mod feed;
+pub mod git;
+pub mod gitea;
+pub mod github;
+pub mod gitlab;
+pub mod goproxy;
+pub mod helm;
+pub mod json;
+pub mod npm;
+pub mod oci;
+pub mod pypi;
+#[cfg(test)]
+pub(crate) mod stub;
+use crate::config::Repo;
+use crate::db::{validators_key, Release, Validators};
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use log::{debug, trace};
+use microkv::MicroKV;
+use reqwest::header::{self, HeaderMap};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::time::Duration;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum SourceType {
+    #[default]
+    Github,
+    GithubTag,
+    Git,
+    Gitlab,
+    GitlabTag,
+    Gitea,
+    #[serde(rename = "crates.io", alias = "crates-io")]
+    CratesIo,
+    Pypi,
+    Npm,
+    GoProxy,
+    Oci,
+    Helm,
+    Feed,
+    Json,
+}
+
+impl SourceType {
+    /// How the source is named in an alert.
+    pub fn label(self) -> &'static str {
+        match self {
+            SourceType::Github | SourceType::GithubTag => "GitHub",
+            SourceType::Git => "Git",
+            SourceType::Gitlab | SourceType::GitlabTag => "GitLab",
+            SourceType::Gitea => "Gitea",
+            SourceType::CratesIo => "crates.io",
+            SourceType::Pypi => "PyPI",
+            SourceType::Npm => "npm",
+            SourceType::GoProxy => "Go Module",
+            SourceType::Oci => "OCI Image",
+            SourceType::Helm => "Helm Chart",
+            SourceType::Feed => "Feed",
+            SourceType::Json => "JSON Endpoint",
+        }
+    }
+}
+
+/// A place the watcher can ask for the releases of a watched target.
+#[async_trait]
+pub trait ReleaseSource: Send + Sync {
+    /// Fetch the latest release of the watched target.
+    async fn latest(&self) -> Result<Release>;
+
+    /// Fetch the recent releases of the watched target, in any order.
+    /// `since` is the publish time of the newest release already recorded,
+    /// sources paging through their history may stop once they reach it.
+    async fn releases(&self, _since: Option<&str>) -> Result<Vec<Release>> {
+        Ok(vec![self.latest().await?])
+    }
+
+    /// Whether the version of a release the source has not set one on may
+    /// be parsed from its tag name. Not so when the tag name is free text.
+    fn versions_from_tags(&self) -> bool {
+        true
+    }
+}
+
+pub fn build(repo: &Repo, ctx: &Context) -> Result<Arc<dyn ReleaseSource>> {
+    let source: Arc<dyn ReleaseSource> = match repo.source_type {
+        SourceType::Github => Arc::new(github::Source::new(repo, ctx.clone())?),
+        SourceType::GithubTag => Arc::new(github::TagSource::new(repo, ctx.clone())?),
+        SourceType::Git => Arc::new(git::Source::new(repo, ctx.clone())),
+        SourceType::Gitlab => Arc::new(gitlab::Source::new(repo, ctx.clone(), false)?),
+        SourceType::GitlabTag => Arc::new(gitlab::Source::new(repo, ctx.clone(), true)?),
+        SourceType::Gitea => Arc::new(gitea::Source::new(repo, ctx.clone())?),
+        SourceType::CratesIo => Arc::new(crates_io::Source::new(repo, ctx.clone())?),
+        SourceType::Pypi => Arc::new(pypi::Source::new(repo, ctx.clone())?),
+        SourceType::Npm => Arc::new(npm::Source::new(repo, ctx.clone())?),
+        SourceType::GoProxy => Arc::new(goproxy::Source::new(repo, ctx.clone())?),
+        SourceType::Oci => Arc::new(oci::Source::new(repo, ctx.clone())?),
+        SourceType::Helm => Arc::new(helm::Source::new(repo, ctx.clone())?),
+        SourceType::Feed => Arc::new(feed::Source::new(repo, ctx.clone())?),
+        SourceType::Json => Arc::new(json::Source::new(repo, ctx.clone())?),
+    };
+    Ok(source)
+}
+
+/// Returned by a source when the upstream reports nothing has changed
+/// since the last processed response.
+#[derive(Debug)]
+pub struct NotModified;
+
+impl fmt::Display for NotModified {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the response has not been modified since the last poll")
+    }
+}
+
+impl std::error::Error for NotModified {}
+
+/// State shared by every source: a single http client, and the db that
+/// request state such as cache validators is kept in.
+#[derive(Clone)]
+pub struct Context {
+    pub client: Client,
+    pub db: MicroKV,
+    pub github_headers: HeaderMap,
+    pub github_credentials: github::Credentials,
+    // fetch GitHub releases in batches through the GraphQL API
+    pub github_graphql: bool,
+    pub github_prefetch: github::Prefetch,
+    pub github_rate_limit: github::RateLimit,
+    // validators of responses that have not been processed by their puller yet
+    pending: Arc<Mutex<HashMap<String, Validators>>>,
+}
+
+impl Context {
+    pub fn new(
+        db: MicroKV,
+        github_headers: HeaderMap,
+        github_credentials: github::Credentials,
+        github_graphql: bool,
+    ) -> Result<Context> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(8))
+            .user_agent("masayil")
+            .build()?;
+        trace!("Build http client complete.");
+        Ok(Context {
+            client,
+            db,
+            github_headers,
+            github_credentials,
+            github_graphql,
+            github_prefetch: Default::default(),
+            github_rate_limit: Default::default(),
+            pending: Default::default(),
+        })
+    }
+
+    /// Send `request` to `url` for the repo `name` as a conditional request,
+    /// with the validators of the last response processed for it. Fails
+    /// with [`NotModified`] when the server answers 304.
+    pub async fn conditional_send(
+        &self,
+        name: &str,
+        url: &str,
+        mut request: RequestBuilder,
+    ) -> Result<Response> {
+        let key = validators_key(name);
+        if self.db.exists(&key)? {
+            let validators: Validators = self.db.get_unwrap(&key)?;
+            if validators.url == url {
+                if let Some(v) = &validators.etag {
+                    request = request.header(header::IF_NONE_MATCH, v);
+                }
+                if let Some(v) = &validators.last_modified {
+                    request = request.header(header::IF_MODIFIED_SINCE, v);
+                }
+            }
+        }
+
+        let resp = request.send().await?;
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            return Err(NotModified.into());
+        }
+        if resp.status().is_success() {
+            let value = |name| {
+                resp.headers()
+                    .get(name)
+                    .and_then(|v| v.to_str().ok())
+                    .map(String::from)
+            };
+            let validators = Validators {
+                url: url.to_string(),
+                etag: value(header::ETAG),
+                last_modified: value(header::LAST_MODIFIED),
+            };
+            self.pending
+                .lock()
+                .unwrap()
+                .insert(name.to_string(), validators);
+        }
+        Ok(resp)
+    }
+
+    /// Persist the validators of the response just processed for the repo
+    /// `name`, so that the next poll can be a conditional request.
+    pub fn commit_validators(&self, name: &str) -> Result<()> {
+        let validators = self.pending.lock().unwrap().remove(name);
+        if let Some(v) = validators {
+            if v.etag.is_some() || v.last_modified.is_some() {
+                self.db.put(validators_key(name), &v)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The configured url of `repo` without a trailing slash, or the `default`
+/// public instance when none is given.
+pub(crate) fn base_url(repo: &Repo, default: &str) -> String {
+    if repo.url.is_empty() {
+        default.to_string()
+    } else {
+        repo.url.trim_end_matches('/').to_string()
+    }
+}
+
+/// Pick the `rel="next"` target out of a `Link` response header.
+pub(crate) fn next_link(link: &str) -> Option<String> {
+    link.split(',').find_map(|part| {
+        let (target, params) = part.split_once(';')?;
+        if !params.split(';').any(|p| p.trim() == "rel=\"next\"") {
+            return None;
+        }
+        let target = target.trim().strip_prefix('<')?.strip_suffix('>')?;
+        Some(target.to_string())
+    })
+}
+
+/// Fetch the JSON pages of a listing from `url`, following the
+/// `rel="next"` link of each for up to `max_pages` pages while `more` says
+/// older items are wanted. `send` requests a page, told whether it may reuse
+/// the validators of the last poll; `what` names the listing in the log.
+pub(crate) async fn paginate<P, S, F, M>(
+    what: &str,
+    url: String,
+    max_pages: u8,
+    mut send: S,
+    mut more: M,
+) -> Result<Vec<P>>
+where
+    P: DeserializeOwned,
+    S: FnMut(String, bool) -> F,
+    F: Future<Output = Result<Response>>,
+    M: FnMut(&P) -> bool,
+{
+    let mut pages = Vec::new();
+    let mut next = Some(url);
+    while let Some(url) = next.take() {
+        // only the first page tells whether anything changed since the last poll
+        let resp = send(url, pages.is_empty()).await?;
+        // registries tend to give the link relative to the host
+        let link = resp
+            .headers()
+            .get(header::LINK)
+            .and_then(|v| v.to_str().ok())
+            .and_then(next_link)
+            .and_then(|v| resp.url().join(&v).ok());
+        let page: P = resp
+            .json()
+            .await
+            .context("Deserialize http response failed!")?;
+        debug!("Requested page {} of the {}", pages.len() + 1, what);
+        let wanted = more(&page);
+        pages.push(page);
+        if wanted && pages.len() < max_pages as usize {
+            next = link.map(String::from);
+        }
+    }
+    trace!("Collected {} page(s) of the {}.", pages.len(), what);
+    Ok(pages)
+}
+
+/// Percent-encode everything but the unreserved characters, so that a value
+/// like `group/project` fits in a single path segment.
+pub(crate) fn encode_component(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for b in value.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+// how many of the newest versions a package registry source reports each poll
+const REGISTRY_VERSION_LIMIT: usize = 30;
+
+#[cfg(test)]
+mod tests {
+    use super::stub::{self, Reply, Stub};
+    use super::*;
+
+    #[tokio::test]
+    async fn validators_are_sent_once_committed() {
+        let server = Stub::start();
+        server.route(
+            "/r",
+            Reply::json("[]")
+                .header("ETag", "\"v1\"")
+                .header("Last-Modified", "Mon, 01 Jan 2024 00:00:00 GMT"),
+        );
+        let ctx = stub::context();
+        let url = format!("{}/r", server.url());
+        let send = || ctx.conditional_send("repo", &url, ctx.client.get(&url));
+
+        send().await.unwrap();
+        // the response has not been processed yet
+        send().await.unwrap();
+        ctx.commit_validators("repo").unwrap();
+        send().await.unwrap();
+        // another url of the repo is not sent the validators
+        let other = format!("{}/r?page=2", server.url());
+        ctx.conditional_send("repo", &other, ctx.client.get(&other))
+            .await
+            .unwrap();
+
+        let received = server.received();
+        assert_eq!(received[0].header("If-None-Match"), None);
+        assert_eq!(received[1].header("If-None-Match"), None);
+        assert_eq!(received[2].header("If-None-Match"), Some("\"v1\""));
+        assert_eq!(
+            received[2].header("If-Modified-Since"),
+            Some("Mon, 01 Jan 2024 00:00:00 GMT")
+        );
+        assert_eq!(received[3].header("If-None-Match"), None);
+    }
+
+    #[tokio::test]
+    async fn not_modified_is_reported_as_such() {
+        let server = Stub::start();
+        server.route("/r", Reply::status(304));
+        let ctx = stub::context();
+        let url = format!("{}/r", server.url());
+        let err = ctx
+            .conditional_send("repo", &url, ctx.client.get(&url))
+            .await
+            .unwrap_err();
+        assert!(err.is::<NotModified>());
+    }
+
+    fn page(items: &str, next: Option<&str>) -> Reply {
+        let reply = Reply::json(items);
+        match next {
+            Some(v) => reply.header(
+                "Link",
+                &format!("<{}>; rel=\"next\", </p/9>; rel=\"last\"", v),
+            ),
+            None => reply,
+        }
+    }
+
+    #[tokio::test]
+    async fn pages_are_followed_while_wanted() {
+        let server = Stub::start();
+        server.route(
+            "/p/1",
+            page("[5, 4]", Some(&format!("{}/p/2", server.url()))),
+        );
+        server.route("/p/2", page("[3, 2]", Some("/p/3")));
+        server.route("/p/3", page("[1, 0]", None));
+        let client = Client::new();
+        let conditional = Mutex::new(Vec::new());
+        let send = |url: String, v: bool| {
+            conditional.lock().unwrap().push(v);
+            let request = client.get(url);
+            async move { Ok(request.send().await?) }
+        };
+
+        let url = format!("{}/p/1", server.url());
+        let pages: Vec<Vec<u8>> = paginate("test", url.clone(), 5, send, |_| true)
+            .await
+            .unwrap();
+        assert_eq!(pages, [[5, 4], [3, 2], [1, 0]]);
+        // only the first page is sent with the validators of the last poll
+        assert_eq!(*conditional.lock().unwrap(), [true, false, false]);
+
+        let pages: Vec<Vec<u8>> = paginate("test", url.clone(), 2, send, |_| true)
+            .await
+            .unwrap();
+        assert_eq!(pages, [[5, 4], [3, 2]]);
+        let pages: Vec<Vec<u8>> = paginate("test", url, 5, send, |v: &Vec<u8>| !v.contains(&4))
+            .await
+            .unwrap();
+        assert_eq!(pages, [[5, 4]]);
+    }
+}
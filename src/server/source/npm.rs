@@ -0,0 +1,208 @@
+use crate::config::Repo;
+use crate::db::{Release, ReleaseDetail};
+use crate::server::version;
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use log::{debug, trace};
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Watches the versions of a package on the npm registry, or on a registry
+/// mirror speaking the same protocol.
+pub struct Source {
+    name: String,
+    // the registry, e.g. https://registry.npmjs.org
+    base: String,
+    package: String,
+    ctx: super::Context,
+}
+
+#[derive(Debug, Deserialize)]
+struct Packument {
+    #[serde(rename = "dist-tags", default)]
+    dist_tags: HashMap<String, String>,
+    // publish time of each version, next to the `created` and `modified` times
+    #[serde(default)]
+    time: HashMap<String, String>,
+    // the manifests are large and not needed, only the published versions
+    #[serde(default)]
+    versions: HashMap<String, IgnoredAny>,
+}
+
+impl Source {
+    pub fn new(repo: &Repo, ctx: super::Context) -> Result<Source> {
+        let package = repo
+            .package
+            .clone()
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| anyhow!("repo {} needs the name of an npm package", repo.name))?;
+        Ok(Source {
+            name: repo.name.clone(),
+            base: super::base_url(repo, NPM_URL),
+            package,
+            ctx,
+        })
+    }
+
+    /// The url of the package on the registry.
+    fn package_url(&self) -> String {
+        // a scoped name keeps its `@` but the slash must be escaped
+        format!(
+            "{}/{}",
+            self.base,
+            super::encode_component(&self.package).replacen("%40", "@", 1)
+        )
+    }
+
+    /// The page of a version: on npmjs.com for the public registry, and the
+    /// version's manifest on any other registry, which has no known website.
+    fn version_url(&self, num: &str) -> String {
+        if self.base == NPM_URL {
+            format!("{}/package/{}/v/{}", NPM_WEB_URL, self.package, num)
+        } else {
+            format!("{}/{}", self.package_url(), num)
+        }
+    }
+
+    async fn packument(&self) -> Result<Packument> {
+        let url = self.package_url();
+        let request = self.ctx.client.get(&url);
+        let resp = self
+            .ctx
+            .conditional_send(&self.name, &url, request)
+            .await?
+            .error_for_status()?;
+        let packument = resp
+            .json()
+            .await
+            .context("Deserialize http response failed!")?;
+        debug!("Requested the versions of the package {}", self.package);
+        Ok(packument)
+    }
+
+    fn release(&self, num: String, published_at: String) -> Release {
+        let detail = ReleaseDetail {
+            release_name: format!("{} {}", self.package, num),
+            prerelease: version::parse(&num).is_some_and(|v| !v.pre.is_empty()),
+            published_at,
+            html_url: self.version_url(&num),
+            tag_name: num,
+        };
+        Release::new(self.base.clone(), self.name.clone(), detail)
+    }
+}
+
+#[async_trait]
+impl super::ReleaseSource for Source {
+    /// The version the `latest` dist-tag points to, which is what
+    /// `npm install` picks.
+    async fn latest(&self) -> Result<Release> {
+        let mut packument = self.packument().await?;
+        let num = packument
+            .dist_tags
+            .remove("latest")
+            .ok_or_else(|| anyhow!("package {} has no latest dist-tag", self.package))?;
+        let published_at = packument.time.remove(&num).unwrap_or_default();
+        Ok(self.release(num, published_at))
+    }
+
+    async fn releases(&self, _since: Option<&str>) -> Result<Vec<Release>> {
+        let packument = self.packument().await?;
+        let mut releases: Vec<Release> = packument
+            .time
+            .into_iter()
+            // versions that were unpublished keep their time entry
+            .filter(|(num, _)| packument.versions.contains_key(num))
+            .map(|(num, published_at)| self.release(num, published_at))
+            .collect();
+        // a version above the `latest` dist-tag was published under another
+        // tag such as `next`, and is not what users get by default
+        if let Some(latest) = packument
+            .dist_tags
+            .get("latest")
+            .and_then(|v| version::parse(v))
+        {
+            for v in releases.iter_mut() {
+                if version::parse(&v.detail.tag_name).is_some_and(|v| v > latest) {
+                    v.detail.prerelease = true;
+                }
+            }
+        }
+        // the registry lists every version ever published, keep the newest
+        releases.sort_by(|a, b| b.detail.published_at.cmp(&a.detail.published_at));
+        releases.truncate(super::REGISTRY_VERSION_LIMIT);
+        trace!(
+            "Collected {} versions of the package {}.",
+            releases.len(),
+            self.package
+        );
+        Ok(releases)
+    }
+}
+
+const NPM_URL: &str = "https://registry.npmjs.org";
+const NPM_WEB_URL: &str = "https://www.npmjs.com";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::source::stub::{self, Reply, Stub};
+    use crate::server::source::ReleaseSource;
+    use serde_json::json;
+
+    fn source(url: &str) -> Source {
+        let repo: Repo = serde_json::from_value(json!({
+            "name": "demo",
+            "type": "npm",
+            "url": url,
+            "package": "@scope/demo",
+        }))
+        .unwrap();
+        Source::new(&repo, stub::context()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn versions_of_a_scoped_package_on_a_mirror() {
+        let registry = Stub::start();
+        let packument = json!({
+            "dist-tags": { "latest": "1.1.0", "next": "2.0.0-beta.1" },
+            "time": {
+                "created": "2024-01-01T00:00:00Z",
+                "modified": "2024-01-04T00:00:00Z",
+                "1.0.0": "2024-01-01T00:00:00Z",
+                "1.0.1": "2024-01-02T00:00:00Z",
+                "1.1.0": "2024-01-03T00:00:00Z",
+                "2.0.0-beta.1": "2024-01-04T00:00:00Z",
+            },
+            // 1.0.1 was unpublished
+            "versions": { "1.0.0": {}, "1.1.0": {}, "2.0.0-beta.1": {} },
+        });
+        registry.route("/@scope%2Fdemo", Reply::json(&packument.to_string()));
+        let source = source(registry.url());
+
+        let releases = source.releases(None).await.unwrap();
+        let found: Vec<(&str, bool)> = releases
+            .iter()
+            .map(|v| (v.detail.tag_name.as_str(), v.detail.prerelease))
+            .collect();
+        assert_eq!(
+            found,
+            [("2.0.0-beta.1", true), ("1.1.0", false), ("1.0.0", false)]
+        );
+        assert_eq!(
+            releases[1].detail.html_url,
+            format!("{}/@scope%2Fdemo/1.1.0", registry.url())
+        );
+        assert_eq!(source.latest().await.unwrap().detail.tag_name, "1.1.0");
+    }
+
+    #[test]
+    fn versions_on_the_public_registry_link_to_npmjs() {
+        let source = source("");
+        assert_eq!(
+            source.version_url("1.1.0"),
+            "https://www.npmjs.com/package/@scope/demo/v/1.1.0"
+        );
+    }
+}
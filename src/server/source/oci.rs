@@ -0,0 +1,397 @@
+use crate::config::Repo;
+use crate::db::{Release, ReleaseDetail};
+use crate::server::filter::Filter;
+use crate::server::version;
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{SecondsFormat, Utc};
+use log::{debug, trace};
+use regex::Regex;
+use reqwest::header;
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Mutex;
+
+/// Watches the tags of a container image through the OCI distribution API,
+/// or the digest a moving tag like `stable` points to.
+pub struct Source {
+    name: String,
+    // the registry api, e.g. https://registry-1.docker.io
+    base: String,
+    // the repository within the registry, e.g. library/nginx
+    image: String,
+    credentials: Option<String>,
+    digest_tag: Option<String>,
+    filter: Filter,
+    ctx: super::Context,
+    // the bearer token handed out by the registry's token service
+    token: Mutex<Option<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TagList {
+    #[serde(default)]
+    tags: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    config: Descriptor,
+}
+
+#[derive(Debug, Deserialize)]
+struct Descriptor {
+    digest: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Token {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+impl Source {
+    pub fn new(repo: &Repo, ctx: super::Context) -> Result<Source> {
+        let package = repo
+            .package
+            .as_deref()
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| anyhow!("repo {} needs the name of an image", repo.name))?;
+        // an image like `ghcr.io/owner/app` names its registry, the way
+        // `docker pull` reads it
+        let (base, image) = match package.split_once('/') {
+            Some((host, image))
+                if repo.url.is_empty() && (host.contains(['.', ':']) || host == "localhost") =>
+            {
+                // like docker, a registry on the loopback may speak plain http
+                let scheme = if host.starts_with("localhost") || host.starts_with("127.") {
+                    "http"
+                } else {
+                    "https"
+                };
+                (format!("{}://{}", scheme, host), image.to_string())
+            }
+            _ => (super::base_url(repo, DOCKER_HUB_URL), package.to_string()),
+        };
+        let base = if base == "https://docker.io" {
+            DOCKER_HUB_URL.to_string()
+        } else {
+            base
+        };
+        // official images live under `library/` on Docker Hub
+        let image = if base == DOCKER_HUB_URL && !image.contains('/') {
+            format!("library/{}", image)
+        } else {
+            image
+        };
+        Ok(Source {
+            name: repo.name.clone(),
+            base,
+            image,
+            credentials: repo.token.clone(),
+            digest_tag: repo.digest_tag.clone(),
+            filter: Filter::from_repo(repo)?,
+            ctx,
+            token: Default::default(),
+        })
+    }
+
+    fn html_url(&self) -> String {
+        if self.base != DOCKER_HUB_URL {
+            return format!("{}/{}", self.base, self.image);
+        }
+        match self.image.strip_prefix("library/") {
+            Some(v) => format!("{}/_/{}", DOCKER_HUB_WEB_URL, v),
+            None => format!("{}/r/{}", DOCKER_HUB_WEB_URL, self.image),
+        }
+    }
+
+    fn build(&self, method: &Method, url: &str) -> RequestBuilder {
+        let request = self
+            .ctx
+            .client
+            .request(method.clone(), url)
+            .header(header::ACCEPT, MANIFEST_ACCEPT);
+        match self.token.lock().unwrap().as_deref() {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// Send a request to the registry, answering an authentication challenge
+    /// once. With `conditional` set the request reuses the validators of the
+    /// last poll.
+    async fn send(&self, method: Method, url: &str, conditional: bool) -> Result<Response> {
+        let request = self.build(&method, url);
+        let resp = if conditional {
+            self.ctx.conditional_send(&self.name, url, request).await?
+        } else {
+            request.send().await?
+        };
+        if resp.status() != StatusCode::UNAUTHORIZED {
+            return Ok(resp.error_for_status()?);
+        }
+
+        let challenge = resp
+            .headers()
+            .get(header::WWW_AUTHENTICATE)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| anyhow!("registry refused the request without a challenge"))?
+            .to_string();
+        let request = if challenge.starts_with("Bearer ") {
+            let token = self.fetch_token(&challenge).await?;
+            *self.token.lock().unwrap() = Some(token);
+            self.build(&method, url)
+        } else {
+            let (user, password) = self
+                .credentials
+                .as_deref()
+                .and_then(|v| v.split_once(':'))
+                .ok_or_else(|| anyhow!("registry asks for a user:password token"))?;
+            self.build(&method, url).basic_auth(user, Some(password))
+        };
+        let resp = if conditional {
+            self.ctx.conditional_send(&self.name, url, request).await?
+        } else {
+            request.send().await?
+        };
+        Ok(resp.error_for_status()?)
+    }
+
+    /// Ask the token service named by a `Bearer` challenge for a pull token.
+    async fn fetch_token(&self, challenge: &str) -> Result<String> {
+        let param = Regex::new(r#"(\w+)="([^"]*)""#)?;
+        let mut realm = None;
+        let mut query = Vec::new();
+        for v in param.captures_iter(challenge) {
+            match &v[1] {
+                "realm" => realm = Some(v[2].to_string()),
+                key @ ("service" | "scope") => query.push((key.to_string(), v[2].to_string())),
+                _ => {}
+            }
+        }
+        let realm = realm.ok_or_else(|| anyhow!("bearer challenge without a realm"))?;
+        let mut request = self.ctx.client.get(&realm).query(&query);
+        if let Some(credentials) = &self.credentials {
+            request = match credentials.split_once(':') {
+                Some((user, password)) => request.basic_auth(user, Some(password)),
+                // a bare token is presented as is
+                None => request.bearer_auth(credentials),
+            };
+        }
+        let token: Token = request
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("Deserialize http response failed!")?;
+        debug!("Fetched a registry token for the {}", self.name);
+        token
+            .token
+            .or(token.access_token)
+            .ok_or_else(|| anyhow!("token service answered without a token"))
+    }
+
+    async fn tags(&self) -> Result<Vec<String>> {
+        let what = format!("tags of the {}", self.name);
+        let url = format!(
+            "{}/v2/{}/tags/list?n={}",
+            self.base, self.image, OCI_PER_PAGE
+        );
+        let pages: Vec<TagList> = super::paginate(
+            &what,
+            url,
+            OCI_MAX_PAGES,
+            |url, conditional| async move { self.send(Method::GET, &url, conditional).await },
+            |_| true,
+        )
+        .await?;
+        Ok(pages
+            .into_iter()
+            .flat_map(|v| v.tags.unwrap_or_default())
+            .collect())
+    }
+
+    async fn digest(&self, tag: &str) -> Result<String> {
+        let url = format!("{}/v2/{}/manifests/{}", self.base, self.image, tag);
+        let resp = self.send(Method::HEAD, &url, false).await?;
+        let digest = resp
+            .headers()
+            .get(DIGEST_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| anyhow!("registry did not report the digest of {}", tag))?;
+        debug!("The tag {} of the {} is {}", tag, self.name, digest);
+        Ok(digest.to_string())
+    }
+
+    /// The config blob of the manifest a tag points to. Artifacts such as
+    /// Helm charts keep their metadata there.
+    pub async fn config(&self, tag: &str) -> Result<Value> {
+        let url = format!("{}/v2/{}/manifests/{}", self.base, self.image, tag);
+        let manifest: Manifest = self
+            .send(Method::GET, &url, false)
+            .await?
+            .json()
+            .await
+            .context("Deserialize http response failed!")?;
+        let url = format!(
+            "{}/v2/{}/blobs/{}",
+            self.base, self.image, manifest.config.digest
+        );
+        let config = self
+            .send(Method::GET, &url, false)
+            .await?
+            .json()
+            .await
+            .context("Deserialize http response failed!")?;
+        Ok(config)
+    }
+
+    fn release(&self, tag: String, published_at: String) -> Release {
+        // a tag pinned to its digest has the version of the tag
+        let bare = tag.split('@').next().unwrap_or_default();
+        let version = version::parse(bare);
+        let detail = ReleaseDetail {
+            release_name: format!("{}:{}", self.image, tag),
+            prerelease: version.as_ref().is_some_and(|v| !v.pre.is_empty()),
+            tag_name: tag,
+            published_at,
+            html_url: self.html_url(),
+        };
+        let mut release = Release::new(self.base.clone(), self.name.clone(), detail);
+        release.version = version.map(|v| v.to_string());
+        release
+    }
+}
+
+#[async_trait]
+impl super::ReleaseSource for Source {
+    async fn latest(&self) -> Result<Release> {
+        self.releases(None)
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow!("image {} has no tag", self.image))
+    }
+
+    async fn releases(&self, _since: Option<&str>) -> Result<Vec<Release>> {
+        // the api carries no dates, so a tag is dated when it is found
+        let now = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+        if let Some(tag) = &self.digest_tag {
+            // pinned the way image references are, so that each digest the
+            // tag moves to is a release of its own
+            let digest = self.digest(tag).await?;
+            return Ok(vec![self.release(format!("{}@{}", tag, digest), now)]);
+        }
+
+        let tags = self.tags().await?;
+        trace!("The {} has {} tags.", self.name, tags.len());
+        // images carry far more tags than any other source, narrow them down
+        // before keeping the newest
+        let mut releases: Vec<Release> = tags
+            .into_iter()
+            .map(|v| self.release(v, now.clone()))
+            .filter(|v| self.filter.matches(v))
+            .collect();
+        if releases.iter().any(|v| v.version.is_some()) {
+            releases.retain(|v| v.version.is_some());
+            releases.sort_by_cached_key(|v| version::parse(&v.detail.tag_name));
+        }
+        releases.drain(..releases.len().saturating_sub(OCI_TAG_LIMIT));
+        Ok(releases)
+    }
+}
+
+const DOCKER_HUB_URL: &str = "https://registry-1.docker.io";
+const DOCKER_HUB_WEB_URL: &str = "https://hub.docker.com";
+const DIGEST_HEADER: &str = "docker-content-digest";
+const MANIFEST_ACCEPT: &str = "application/vnd.oci.image.index.v1+json, \
+    application/vnd.oci.image.manifest.v1+json, \
+    application/vnd.docker.distribution.manifest.list.v2+json, \
+    application/vnd.docker.distribution.manifest.v2+json";
+const OCI_PER_PAGE: u16 = 1000;
+const OCI_MAX_PAGES: u8 = 5;
+// how many of the newest tags are turned into releases each poll
+const OCI_TAG_LIMIT: usize = 10;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::source::stub::{self, Reply, Stub};
+    use crate::server::source::ReleaseSource;
+    use serde_json::json;
+
+    fn repo(registry: &Stub, extra: Value) -> Repo {
+        let mut repo = json!({
+            "name": "app",
+            "type": "oci",
+            "package": format!("{}/team/app", registry.host()),
+        });
+        repo.as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(repo).unwrap()
+    }
+
+    #[tokio::test]
+    async fn moving_tag_is_pinned_to_its_digest() {
+        let registry = Stub::start();
+        let manifest = "/v2/team/app/manifests/1.25-rc.1";
+        registry.route(
+            manifest,
+            Reply::json("").header(DIGEST_HEADER, "sha256:aaa"),
+        );
+        let repo = repo(&registry, json!({ "digestTag": "1.25-rc.1" }));
+        let source = Source::new(&repo, stub::context()).unwrap();
+
+        let releases = source.releases(None).await.unwrap();
+        assert_eq!(releases.len(), 1);
+        assert_eq!(releases[0].detail.tag_name, "1.25-rc.1@sha256:aaa");
+        assert_eq!(releases[0].version.as_deref(), Some("1.25.0-rc.1"));
+        assert!(releases[0].detail.prerelease);
+
+        registry.route(
+            manifest,
+            Reply::json("").header(DIGEST_HEADER, "sha256:bbb"),
+        );
+        let releases = source.releases(None).await.unwrap();
+        assert_eq!(releases[0].detail.tag_name, "1.25-rc.1@sha256:bbb");
+        assert_eq!(registry.requests()[0], format!("HEAD {}", manifest));
+    }
+
+    #[tokio::test]
+    async fn tags_are_paged_behind_a_bearer_challenge() {
+        let registry = Stub::start();
+        let challenge = format!(
+            r#"Bearer realm="{}/token",service="stub",scope="repository:team/app:pull""#,
+            registry.url()
+        );
+        registry.protect("Bearer t0k", &challenge, "/token");
+        registry.route("/token", Reply::json(r#"{"token":"t0k"}"#));
+        let source = Source::new(&repo(&registry, json!({})), stub::context()).unwrap();
+        registry.route(
+            "/v2/team/app/tags/list?n=1000",
+            Reply::json(r#"{"tags":["1.0.0","latest","1.2.0"]}"#).header(
+                "Link",
+                "</v2/team/app/tags/list?n=1000&last=1.2.0>; rel=\"next\"",
+            ),
+        );
+        registry.route(
+            "/v2/team/app/tags/list?n=1000&last=1.2.0",
+            Reply::json(r#"{"tags":["1.10.0","2.0.0-beta.1"]}"#),
+        );
+        let releases = source.releases(None).await.unwrap();
+        assert!(registry
+            .requests()
+            .iter()
+            .any(|v| v.starts_with("GET /token?") && v.contains("scope=repository")));
+        let tags: Vec<&str> = releases
+            .iter()
+            .map(|v| v.detail.tag_name.as_str())
+            .collect();
+        // tags that are not versions are dropped once any tag is one
+        assert_eq!(tags, ["1.0.0", "1.2.0", "1.10.0", "2.0.0-beta.1"]);
+        assert!(releases[3].detail.prerelease);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::config::Repo;
+use crate::db::{Release, ReleaseDetail};
+use crate::server::version;
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use log::{debug, trace};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Watches the versions of a Python package through the JSON API of PyPI,
+/// or of a mirror serving the same API.
+pub struct Source {
+    name: String,
+    // the registry, e.g. https://pypi.org
+    base: String,
+    package: String,
+    ctx: super::Context,
+}
+
+#[derive(Debug, Deserialize)]
+struct Project {
+    info: ProjectInfo,
+    releases: HashMap<String, Vec<ReleaseFile>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProjectInfo {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseFile {
+    upload_time_iso_8601: String,
+    #[serde(default)]
+    yanked: bool,
+}
+
+impl Source {
+    pub fn new(repo: &Repo, ctx: super::Context) -> Result<Source> {
+        let package = repo
+            .package
+            .clone()
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| anyhow!("repo {} needs the name of a python package", repo.name))?;
+        Ok(Source {
+            name: repo.name.clone(),
+            base: super::base_url(repo, PYPI_URL),
+            package,
+            ctx,
+        })
+    }
+}
+
+#[async_trait]
+impl super::ReleaseSource for Source {
+    async fn latest(&self) -> Result<Release> {
+        let mut releases = self.releases(None).await?;
+        releases.sort_by_cached_key(|v| version::parse(&v.detail.tag_name));
+        releases
+            .pop()
+            .ok_or_else(|| anyhow!("package {} has no published version", self.package))
+    }
+
+    async fn releases(&self, _since: Option<&str>) -> Result<Vec<Release>> {
+        let url = format!(
+            "{}/pypi/{}/json",
+            self.base,
+            super::encode_component(&self.package)
+        );
+        let request = self.ctx.client.get(&url);
+        let resp = self
+            .ctx
+            .conditional_send(&self.name, &url, request)
+            .await?
+            .error_for_status()?;
+        let project: Project = resp
+            .json()
+            .await
+            .context("Deserialize http response failed!")?;
+        debug!("Requested the versions of the package {}", self.package);
+
+        let mut releases: Vec<Release> = project
+            .releases
+            .into_iter()
+            .filter_map(|(num, files)| {
+                // a version is out once its first file is uploaded, and
+                // withdrawn once every file is yanked
+                if files.iter().all(|v| v.yanked) {
+                    return None;
+                }
+                let published_at = files.into_iter().map(|v| v.upload_time_iso_8601).min()?;
+                let detail = ReleaseDetail {
+                    release_name: format!("{} {}", project.info.name, num),
+                    prerelease: version::parse(&num).is_some_and(|v| !v.pre.is_empty()),
+                    published_at,
+                    html_url: format!("{}/project/{}/{}/", self.base, self.package, num),
+                    tag_name: num,
+                };
+                Some(Release::new(self.base.clone(), self.name.clone(), detail))
+            })
+            .collect();
+        // the api lists every version ever published, keep the newest
+        releases.sort_by(|a, b| b.detail.published_at.cmp(&a.detail.published_at));
+        releases.truncate(super::REGISTRY_VERSION_LIMIT);
+        trace!(
+            "Collected {} versions of the package {} that are not yanked.",
+            releases.len(),
+            self.package
+        );
+        Ok(releases)
+    }
+}
+
+const PYPI_URL: &str = "https://pypi.org";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::source::stub::{self, Reply, Stub};
+    use crate::server::source::ReleaseSource;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn post_release_is_stable_and_yanked_versions_are_dropped() {
+        let pypi = Stub::start();
+        let file =
+            |time: &str, yanked: bool| json!({ "upload_time_iso_8601": time, "yanked": yanked });
+        let project = json!({
+            "info": { "name": "Demo" },
+            "releases": {
+                "1.0": [file("2024-01-01T00:00:00Z", false)],
+                "1.0.post1": [file("2024-01-02T00:00:00Z", false)],
+                "1.1rc1": [file("2024-01-03T00:00:00Z", false), file("2024-01-03T00:01:00Z", true)],
+                "1.1": [file("2024-01-04T00:00:00Z", true)],
+                "0.9": [],
+            },
+        });
+        pypi.route("/pypi/demo/json", Reply::json(&project.to_string()));
+        let repo: Repo = serde_json::from_value(json!({
+            "name": "demo",
+            "type": "pypi",
+            "url": pypi.url(),
+            "package": "demo",
+        }))
+        .unwrap();
+        let source = Source::new(&repo, stub::context()).unwrap();
+
+        let mut releases = source.releases(None).await.unwrap();
+        releases.sort_by(|a, b| a.detail.published_at.cmp(&b.detail.published_at));
+        let found: Vec<(&str, bool)> = releases
+            .iter()
+            .map(|v| (v.detail.tag_name.as_str(), v.detail.prerelease))
+            .collect();
+        assert_eq!(
+            found,
+            [("1.0", false), ("1.0.post1", false), ("1.1rc1", true)]
+        );
+        assert_eq!(releases[0].detail.published_at, "2024-01-01T00:00:00Z");
+    }
+}
@@ -0,0 +1,233 @@
+//! A stand-in HTTP server on the loopback for the tests of the sources.
+
+use super::Context;
+use microkv::MicroKV;
+use reqwest::header::HeaderMap;
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+#[derive(Debug, Clone)]
+pub struct Reply {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Reply {
+    pub fn json(body: &str) -> Reply {
+        Reply {
+            status: 200,
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: body.to_string(),
+        }
+    }
+
+    /// A reply with nothing but the status code.
+    pub fn status(status: u16) -> Reply {
+        Reply {
+            status,
+            headers: Vec::new(),
+            body: String::new(),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Reply {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// A request as the stub received it.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub target: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Request {
+    /// The value of the header `name`, which is matched without case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Answers each request with the reply set for its path, matched with the
+/// query first and without it next, and 404 otherwise.
+pub struct Stub {
+    url: String,
+    routes: Arc<Mutex<HashMap<String, VecDeque<Reply>>>>,
+    requests: Arc<Mutex<Vec<Request>>>,
+    guard: Arc<Mutex<Option<Guard>>>,
+}
+
+// refuses requests without the expected Authorization header
+#[derive(Debug, Clone)]
+struct Guard {
+    authorization: String,
+    challenge: String,
+    open_path: String,
+}
+
+impl Stub {
+    pub fn start() -> Stub {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let routes: Arc<Mutex<HashMap<String, VecDeque<Reply>>>> = Default::default();
+        let requests: Arc<Mutex<Vec<Request>>> = Default::default();
+        let guard: Arc<Mutex<Option<Guard>>> = Default::default();
+        let (shared_routes, shared_requests, shared_guard) =
+            (routes.clone(), requests.clone(), guard.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                if reader.read_line(&mut line).is_err() {
+                    continue;
+                }
+                let mut parts = line.split_whitespace();
+                let method = parts.next().unwrap_or_default().to_string();
+                let target = parts.next().unwrap_or_default().to_string();
+                let mut headers = Vec::new();
+                loop {
+                    let mut header = String::new();
+                    if reader.read_line(&mut header).unwrap_or(0) == 0 || header == "\r\n" {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        headers.push((name.to_string(), value.trim().to_string()));
+                    }
+                }
+                let mut request = Request {
+                    method,
+                    target,
+                    headers,
+                    body: String::new(),
+                };
+                let length = request
+                    .header("content-length")
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0);
+                let mut body = vec![0; length];
+                if reader.read_exact(&mut body).is_err() {
+                    continue;
+                }
+                request.body = String::from_utf8_lossy(&body).into_owned();
+                let authorization = request.header("authorization").map(String::from);
+                let (method, target) = (request.method.clone(), request.target.clone());
+                shared_requests.lock().unwrap().push(request);
+                let path = target.split('?').next().unwrap_or_default();
+                let guard = shared_guard.lock().unwrap().clone();
+                let reply = match guard {
+                    Some(v)
+                        if authorization.as_ref() != Some(&v.authorization)
+                            && v.open_path != path =>
+                    {
+                        Some(Reply::status(401).header("WWW-Authenticate", &v.challenge))
+                    }
+                    _ => {
+                        let mut routes = shared_routes.lock().unwrap();
+                        let key = if routes.contains_key(&target) {
+                            target.as_str()
+                        } else {
+                            path
+                        };
+                        // the last reply of a route answers every request after it
+                        routes.get_mut(key).and_then(|v| match v.len() {
+                            1 => v.front().cloned(),
+                            _ => v.pop_front(),
+                        })
+                    }
+                };
+                let reply = reply.unwrap_or(Reply::status(404));
+                let mut head = format!("HTTP/1.1 {} Stub\r\n", reply.status);
+                for (k, v) in reply.headers.iter() {
+                    head.push_str(&format!("{}: {}\r\n", k, v));
+                }
+                head.push_str(&format!(
+                    "Content-Length: {}\r\nConnection: close\r\n\r\n",
+                    reply.body.len()
+                ));
+                let _ = stream.write_all(head.as_bytes());
+                if method != "HEAD" {
+                    let _ = stream.write_all(reply.body.as_bytes());
+                }
+            }
+        });
+        Stub {
+            url,
+            routes,
+            requests,
+            guard,
+        }
+    }
+
+    /// The base url of the server, e.g. `http://127.0.0.1:41234`.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The host and port of the server.
+    pub fn host(&self) -> &str {
+        self.url.trim_start_matches("http://")
+    }
+
+    pub fn route(&self, path: &str, reply: Reply) {
+        self.replies(path, vec![reply]);
+    }
+
+    /// Answer the requests to `path` with `replies` in turn, and with the
+    /// last of them once the others are used up.
+    pub fn replies(&self, path: &str, replies: Vec<Reply>) {
+        self.routes
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), replies.into());
+    }
+
+    /// Refuse every request but those to `open_path` with a 401 carrying
+    /// `challenge`, unless it is sent with the `authorization` header.
+    pub fn protect(&self, authorization: &str, challenge: &str, open_path: &str) {
+        *self.guard.lock().unwrap() = Some(Guard {
+            authorization: authorization.to_string(),
+            challenge: challenge.to_string(),
+            open_path: open_path.to_string(),
+        });
+    }
+
+    /// The `METHOD target` of every request received so far.
+    pub fn requests(&self) -> Vec<String> {
+        self.received()
+            .into_iter()
+            .map(|v| format!("{} {}", v.method, v.target))
+            .collect()
+    }
+
+    /// Every request received so far, with its headers and body.
+    pub fn received(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// A context over an in-memory db of its own.
+pub fn context() -> Context {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let name = format!(
+        "watch-release-test-{}-{}",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    );
+    let db = MicroKV::open_with_base_path(&name, std::env::temp_dir()).unwrap();
+    Context::new(db, HeaderMap::new(), Default::default(), false).unwrap()
+}
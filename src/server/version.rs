@@ -0,0 +1,183 @@
+use semver::{BuildMetadata, Prerelease, Version};
+
+/// Parse a tag name as a semantic version.
+///
+/// Besides strict semver this accepts the forms commonly seen in tags: a
+/// `v` or project name prefix (`v1.2.3`, `release-1.2`, `go1.21.0`), missing
+/// minor or patch parts, a fourth numeric part (kept as build metadata) and
+/// pre-release suffixes written without a dash (`1.0rc1`, `2.0.0.beta2`).
+/// A PEP 440 post-release (`1.0.post1`) is kept as build metadata, so that
+/// it is no pre-release and ranks right after the release it amends.
+pub fn parse(tag: &str) -> Option<Version> {
+    let tag = tag.trim();
+    let start = tag.find(|c: char| c.is_ascii_digit())?;
+    let (prefix, rest) = tag.split_at(start);
+    if !prefix
+        .chars()
+        .all(|c| c.is_ascii_alphabetic() || matches!(c, '-' | '_' | '/' | '.'))
+    {
+        return None;
+    }
+    if let Ok(v) = Version::parse(rest) {
+        return Some(v);
+    }
+
+    let core_end = rest
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(rest.len());
+    let (core, suffix) = rest.split_at(core_end);
+    let parts = core
+        .trim_end_matches('.')
+        .split('.')
+        .map(|v| v.parse::<u64>().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    if parts.len() > 4 {
+        return None;
+    }
+
+    let mut version = Version::new(
+        parts[0],
+        parts.get(1).copied().unwrap_or(0),
+        parts.get(2).copied().unwrap_or(0),
+    );
+    let (pre, build) = match suffix.split_once('+') {
+        Some((pre, build)) => (pre, Some(build)),
+        None => (suffix, None),
+    };
+    let pre = pre.trim_start_matches(['-', '_', '.']);
+    let (pre, post) = split_post(pre);
+    if !pre.is_empty() {
+        version.pre = Prerelease::new(&sanitize(pre)).ok()?;
+    }
+    let mut build: Vec<String> = build.map(sanitize).into_iter().collect();
+    if let Some(v) = post {
+        build.insert(0, format!("post.{}", v));
+    }
+    if let Some(v) = parts.get(3) {
+        build.insert(0, v.to_string());
+    }
+    if !build.is_empty() {
+        version.build = BuildMetadata::new(&build.join(".")).ok()?;
+    }
+    Some(version)
+}
+
+// Split a trailing PEP 440 post-release segment (`post1`, `.post.2`, `post`)
+// off a suffix, returning what is left and the post-release number.
+fn split_post(suffix: &str) -> (&str, Option<u64>) {
+    let lower = suffix.to_ascii_lowercase();
+    let Some(i) = lower.rfind("post") else {
+        return (suffix, None);
+    };
+    let head = suffix[..i].trim_end_matches(['-', '_', '.']);
+    // `post` must start a segment of its own, not end a word like `repost`
+    if i > 0 && head.len() == i {
+        return (suffix, None);
+    }
+    let num = suffix[i + 4..].trim_start_matches(['-', '_', '.']);
+    if !num.chars().all(|c| c.is_ascii_digit()) {
+        return (suffix, None);
+    }
+    // an implicit number is 0
+    let num = if num.is_empty() {
+        Some(0)
+    } else {
+        num.parse().ok()
+    };
+    match num {
+        Some(v) => (head, Some(v)),
+        None => (suffix, None),
+    }
+}
+
+// Turn a free-form suffix into dot separated semver identifiers.
+fn sanitize(s: &str) -> String {
+    s.split(|c: char| !c.is_ascii_alphanumeric() && c != '-')
+        .filter(|v| !v.is_empty())
+        .map(|v| {
+            if v.chars().all(|c| c.is_ascii_digit()) {
+                let v = v.trim_start_matches('0');
+                if v.is_empty() {
+                    "0"
+                } else {
+                    v
+                }
+            } else {
+                v
+            }
+        })
+        .collect::<Vec<&str>>()
+        .join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_parse_as_versions() {
+        let cases = [
+            ("1.2.3", "1.2.3"),
+            ("v1.2.3", "1.2.3"),
+            ("V1.2.3", "1.2.3"),
+            (" v1.2.3 ", "1.2.3"),
+            ("release-1.2", "1.2.0"),
+            ("go1.21.0", "1.21.0"),
+            ("mylib/v0.4.1", "0.4.1"),
+            ("1", "1.0.0"),
+            ("1.2", "1.2.0"),
+            ("1.2.", "1.2.0"),
+            ("1.2.3-rc.1", "1.2.3-rc.1"),
+            ("v1.2.3-rc.1+build.5", "1.2.3-rc.1+build.5"),
+            ("1.0rc1", "1.0.0-rc1"),
+            ("2.0.0.beta2", "2.0.0-beta2"),
+            ("1.2-beta_01", "1.2.0-beta.1"),
+            ("1.2.3.4", "1.2.3+4"),
+            ("1.0.post1", "1.0.0+post.1"),
+            ("1.0-post", "1.0.0+post.0"),
+            ("1.0rc1.post2", "1.0.0-rc1+post.2"),
+            ("1.2.3.4.post1", "1.2.3+4.post.1"),
+            ("1.0-repost", "1.0.0-repost"),
+        ];
+        for (tag, want) in cases {
+            let got = parse(tag).map(|v| v.to_string());
+            assert_eq!(got.as_deref(), Some(want), "{}", tag);
+        }
+    }
+
+    #[test]
+    fn other_tags_are_no_versions() {
+        let cases = [
+            "",
+            "latest",
+            "nightly",
+            "v",
+            "1.2.3.4.5",
+            "1..2",
+            "99999999999999999999.0.0",
+            "1.99999999999999999999",
+        ];
+        for tag in cases {
+            assert_eq!(parse(tag), None, "{}", tag);
+        }
+    }
+
+    #[test]
+    fn versions_rank_by_release_then_post_release() {
+        let ranked = [
+            "1.0rc1",
+            "1.0",
+            "1.0.post1",
+            "1.0.post2",
+            "1.0.post10",
+            "1.0.1-alpha",
+            "1.0.1",
+            "v1.10",
+        ];
+        let versions: Vec<Version> = ranked.iter().map(|v| parse(v).unwrap()).collect();
+        for pair in versions.windows(2) {
+            assert!(pair[0] < pair[1], "{} < {}", pair[0], pair[1]);
+        }
+        assert!(parse("1.0.post1").unwrap().pre.is_empty());
+    }
+}
@@ -1,119 +1,273 @@
 use crate::config::{Repo, RETRY};
-use crate::db::{key_in_db_status, KeyFlag, Release, ReleaseDetail};
+use crate::db::{get_release, key_in_db_status, seen_key, KeyFlag, Release, SEEN_CAPACITY};
+use crate::server::alert::Alert;
+use crate::server::filter::Filter;
+use crate::server::source::github::{self, graphql};
+use crate::server::source::{self, NotModified, ReleaseSource, SourceType};
+use crate::server::version;
 use crate::shutdown::Shutdown;
 use anyhow::{anyhow, Context, Result};
 use log::{debug, error, info, trace};
-use microkv::MicroKV;
-use reqwest::header::HeaderMap;
-use reqwest::{self, Client};
-use serde::{Deserialize, Serialize};
+use semver::Version;
 use std::sync::Arc;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::Semaphore;
 use tokio::time::{self, Duration};
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Clone)]
 pub struct Puller {
     pub retry_interval: u64,
     pub repo: Repo,
-    pub db: MicroKV,
+    pub ctx: source::Context,
     pub retry: u8,
+    pub source: Arc<dyn ReleaseSource>,
+    pub filter: Filter,
 }
 
-type PullerList = Vec<Puller>;
+pub type PullerList = Vec<Puller>;
 
 impl Puller {
-    pub fn new(db: MicroKV, retry_interval: u64, repo: Repo, retry: u8) -> Puller {
+    pub fn new(
+        ctx: source::Context,
+        retry_interval: u64,
+        repo: Repo,
+        retry: u8,
+        source: Arc<dyn ReleaseSource>,
+        filter: Filter,
+    ) -> Puller {
         Puller {
             retry_interval,
             repo,
-            db,
+            ctx,
             retry,
+            source,
+            filter,
         }
     }
 
     fn update_retry(&mut self) {
-        self.retry = self.retry + 1;
-    }
-
-    async fn pull(&self, release_tx: Sender<Release>, headers: HeaderMap) -> Result<()> {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(8))
-            .user_agent("masayil")
-            .default_headers(headers)
-            .build()?;
-        trace!("Build http client complete.");
-        let resp = client.get(&self.repo.url).send().await?.text().await?;
-        debug!(
-            "Requested the latest release version of the {}",
-            self.repo.name
+        self.retry += 1;
+    }
+
+    fn exists(&self, key: &str) -> Result<bool> {
+        match key_in_db_status(self.ctx.db.clone(), key) {
+            KeyFlag::Exist => Ok(true),
+            KeyFlag::NotExist => Ok(false),
+            KeyFlag::FnFail => {
+                error!("Query key:{} in the db failed.", key);
+                Err(anyhow!("Get error when execute db::exists!!"))
+            }
+        }
+    }
+
+    async fn pull(&self, release_tx: Sender<Alert>) -> Result<()> {
+        let name = self.repo.name.as_str();
+        let stored = if self.exists(name)? {
+            let mut value = get_release(&self.ctx.db, name)?;
+            trace!("Get the value of key:{}", name);
+            // a release stored before versions were tracked
+            if value.version.is_none() && self.source.versions_from_tags() {
+                value.version = version::parse(&value.detail.tag_name).map(|v| v.to_string());
+            }
+            Some(value)
+        } else {
+            None
+        };
+        let since = stored.as_ref().map(|v| v.detail.published_at.as_str());
+        let mut releases = match self.source.releases(since).await {
+            Ok(v) => v,
+            Err(e) if e.is::<NotModified>() => {
+                info!(
+                    "Repo: {} has not the new release version. Nothing changed since the last poll",
+                    name
+                );
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        trace!(
+            "Fetched {} recent releases of {} from its source.",
+            releases.len(),
+            name
         );
-        let detail: ReleaseDetail =
-            serde_json::from_str(resp.as_str()).context("Deserialize http resopnes failed!")?;
-        trace!("Deserialized the http resopnes to crate::db::ReleaseDetail.");
-        let release = Release::new(self.repo.url.clone(), self.repo.name.clone(), detail);
-
-        match key_in_db_status(self.db.clone(), self.repo.name.as_str()) {
-            KeyFlag::Exist => {
-                let value: Release = self.db.get_unwrap(&self.repo.name)?;
-                trace!("Get the value of key:{}", self.repo.name);
-                if value != release {
-                    let _ = release_tx.send(release.clone()).await?;
-                    info!(
-                        "send {} latest release version to the alert channel",
-                        self.repo.name
-                    );
+        // unless the source knows the version better than the tag tells it
+        if self.source.versions_from_tags() {
+            for v in releases.iter_mut().filter(|v| v.version.is_none()) {
+                v.version = version::parse(&v.detail.tag_name).map(|v| v.to_string());
+            }
+        }
+        releases.retain(|v| self.filter.matches(v));
+        sort_releases(&mut releases);
+        let mut latest = match releases.last() {
+            Some(v) => v.clone(),
+            None => {
+                info!(
+                    "Repo: {} has not published any release matching its filters yet.",
+                    name
+                );
+                self.ctx.commit_validators(name)?;
+                return Ok(());
+            }
+        };
+        if let Some(value) = stored.as_ref().filter(|v| self.filter.matches(v)) {
+            // never move the recorded version backwards, e.g. when the
+            // upstream rolls "latest" back to an older release
+            if let (Some(cur), Some(new)) = (semver_of(value), semver_of(&latest)) {
+                if new < cur {
+                    latest = value.clone();
+                }
+            }
+        }
+
+        let seen_key = seen_key(name);
+        let seen: Option<Vec<String>> = if self.exists(&seen_key)? {
+            Some(self.ctx.db.get_unwrap(&seen_key)?)
+        } else {
+            None
+        };
+        let mut seen = match (&stored, seen) {
+            (_, Some(seen)) => seen,
+            // the repo was recorded before seen ids were kept, everything
+            // up to the stored release has already been alerted
+            (Some(value), None) => releases
+                .iter()
+                .filter(|v| v.detail.published_at <= value.detail.published_at)
+                .map(|v| v.id().to_string())
+                .chain(std::iter::once(value.id().to_string()))
+                .collect(),
+            (None, None) => Vec::new(),
+        };
+
+        match stored {
+            Some(value) => {
+                let mut current = value;
+                let mut found = false;
+                for v in releases
+                    .iter()
+                    .filter(|v| !seen.iter().any(|id| id == v.id()))
+                {
+                    // a current release recorded before the filters were
+                    // changed says nothing about the line being followed
+                    let gate = Some(&current)
+                        .filter(|v| self.filter.matches(v))
+                        .and_then(semver_of);
+                    // the same version again is a release of its own, such as
+                    // a moving image tag pointing to a new digest
+                    if let (Some(cur), Some(new)) = (gate, semver_of(v)) {
+                        if new < cur {
+                            info!(
+                                "Repo: {} ignore the release {}. Version {} is older than the current version {}",
+                                name, v.detail.tag_name, new, cur
+                            );
+                            continue;
+                        }
+                    }
+                    let mut event = v.clone();
+                    // the same version again is told apart by its tag
+                    event.previous_version = current
+                        .version
+                        .clone()
+                        .filter(|v| event.version.as_ref() != Some(v))
+                        .or_else(|| Some(current.detail.tag_name.clone()));
+                    release_tx
+                        .send(Alert {
+                            release: event,
+                            source: self.repo.source_type,
+                        })
+                        .await?;
                     info!(
-                        "Repo: {} found the new release version. Current version is {}. The latest version is {}",
-                        self.repo.name,value.detail.release_name, release.detail.release_name
+                        "Repo: {} found the new release version. Current version is {}. The new version is {}",
+                        name, current.detail.release_name, v.detail.release_name
                     );
-                    let _ = self.db.put(&self.repo.name, &release)?;
-                    debug!("Update key:{} in db.", self.repo.name);
-                } else {
+                    current = v.clone();
+                    found = true;
+                }
+                if !found {
                     info!(
                         "Repo: {} has not the new release version. Current version is {}",
-                        self.repo.name, value.detail.release_name
+                        name, current.detail.release_name
                     );
                 }
             }
-            KeyFlag::NotExist => {
+            None => {
                 info!(
                     "Repo: {} found the new release version. The latest version is {}",
-                    self.repo.name, release.detail.release_name
+                    name, latest.detail.release_name
                 );
-                let _ = self.db.put(&self.repo.name, &release)?;
-                debug!("Update key:{} in db.", self.repo.name);
             }
-            KeyFlag::FnFail => {
-                error!("Query key:{} in the db failed.", self.repo.name);
-                return Err(anyhow!("Get error when execute db::exists!!"));
+        }
+
+        for v in releases.iter() {
+            if !seen.iter().any(|id| id == v.id()) {
+                seen.push(v.id().to_string());
             }
         }
+        if seen.len() > SEEN_CAPACITY {
+            seen.drain(..seen.len() - SEEN_CAPACITY);
+        }
+        self.ctx.db.put(&seen_key, &seen)?;
+        self.ctx.db.put(name, &latest)?;
+        self.ctx.commit_validators(name)?;
+        debug!("Update key:{} in db.", name);
         Ok(())
     }
 }
 
+fn semver_of(release: &Release) -> Option<Version> {
+    release
+        .version
+        .as_deref()
+        .and_then(|v| Version::parse(v).ok())
+}
+
+/// Order releases oldest first: by version when every tag is a version,
+/// otherwise by publish time.
+fn sort_releases(releases: &mut [Release]) {
+    if releases.iter().all(|v| semver_of(v).is_some()) {
+        releases.sort_by_cached_key(|v| (semver_of(v), v.detail.published_at.clone()));
+    } else {
+        releases.sort_by(|a, b| a.detail.published_at.cmp(&b.detail.published_at));
+    }
+}
+
+pub fn build_puller_list(
+    repo_list: Vec<Repo>,
+    ctx: &source::Context,
+    retry_interval: u64,
+) -> Result<PullerList> {
+    let mut puller_list = PullerList::new();
+    for v in repo_list.into_iter() {
+        let source = source::build(&v, ctx)
+            .with_context(|| format!("cannot build the release source of repo {}", v.name))?;
+        let filter = Filter::from_repo(&v)
+            .with_context(|| format!("cannot build the release filter of repo {}", v.name))?;
+        puller_list.push(Puller::new(
+            ctx.clone(),
+            retry_interval,
+            v,
+            1,
+            source,
+            filter,
+        ));
+    }
+    Ok(puller_list)
+}
+
 pub async fn do_watch(
-    headers: HeaderMap,
+    puller_list: PullerList,
+    ctx: source::Context,
     period: u64,
-    retry_interval: u64,
-    repo_list: Vec<Repo>,
-    db: MicroKV,
     mut notify_shutdown_watch: Shutdown,
     _shutdown_complete_tx_watch: Sender<()>,
-    release_tx: Sender<Release>,
+    release_tx: Sender<Alert>,
 ) {
-    let mut puller_list = PullerList::new();
-    for v in repo_list.into_iter() {
-        puller_list.push(Puller::new(db.clone(), retry_interval, v, 1));
-    }
     while !notify_shutdown_watch.is_shutdown() {
         info!("Start doing watch repo release.");
         tokio::select! {
             _ = notify_shutdown_watch.recv() => {
                 info!("Watch module is stopping.");
             },
-            _ = try_watch(puller_list.clone(),period,release_tx.clone(),headers.clone())=>{
+            _ = try_watch(puller_list.clone(),&ctx,period,release_tx.clone())=>{
             },
         }
     }
@@ -121,36 +275,46 @@ pub async fn do_watch(
 
 async fn try_watch(
     puller_list: PullerList,
+    ctx: &source::Context,
     period: u64,
-    release_tx: Sender<Release>,
-    headers: HeaderMap,
+    release_tx: Sender<Alert>,
 ) {
+    if ctx.github_graphql {
+        let targets = puller_list
+            .iter()
+            .filter(|v| {
+                matches!(
+                    v.repo.source_type,
+                    SourceType::Github | SourceType::GithubTag
+                )
+            })
+            .filter_map(|v| {
+                Some(graphql::Target {
+                    name: v.repo.name.clone(),
+                    url: github::repo_url(&v.repo).ok()?,
+                    tags: v.repo.source_type == SourceType::GithubTag,
+                })
+            })
+            .collect();
+        graphql::prefetch(ctx, targets).await;
+    }
     let mut spawn_queue = Vec::new();
     let semaphore = Arc::new(Semaphore::new(8));
     for mut v in puller_list.into_iter() {
         if semaphore.acquire().await.is_ok() {
             let release_tx = release_tx.clone();
-            let headers = headers.clone();
             let handler = tokio::spawn(async move {
-                loop {
-                    // let release_tx = release_tx.clone();
-                    match v.pull(release_tx.clone(), headers.clone()).await {
-                        Err(e) => {
-                            error!("Pull {} release info failed. Error: {}", v.repo.name, e);
-                            if v.retry > RETRY {
-                                break;
-                            }
-                            info!(
-                                "Retry pull {} release info after {} seconds!",
-                                v.repo.name, v.retry_interval
-                            );
-                            v.update_retry();
-                            time::sleep(Duration::from_secs(v.retry_interval.into())).await;
-                        }
-                        Ok(_) => {
-                            break;
-                        }
+                while let Err(e) = v.pull(release_tx.clone()).await {
+                    error!("Pull {} release info failed. Error: {}", v.repo.name, e);
+                    if v.retry > RETRY {
+                        break;
                     }
+                    info!(
+                        "Retry pull {} release info after {} seconds!",
+                        v.repo.name, v.retry_interval
+                    );
+                    v.update_retry();
+                    time::sleep(Duration::from_secs(v.retry_interval)).await;
                 }
             });
             spawn_queue.push(handler);
@@ -165,6 +329,248 @@ async fn try_watch(
         let _ = v.await;
     }
     info!("Complete doing watch repo release.");
+    ctx.github_rate_limit.log_budget();
     semaphore.close();
     time::sleep(Duration::from_secs(period)).await;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::source::stub::{self, Reply, Stub};
+    use serde_json::json;
+    use tokio::sync::mpsc;
+
+    fn puller(repo: serde_json::Value) -> (Puller, source::Context) {
+        let ctx = stub::context();
+        let repo: Repo = serde_json::from_value(repo).unwrap();
+        let mut list = build_puller_list(vec![repo], &ctx, 1).unwrap();
+        (list.remove(0), ctx)
+    }
+
+    // a GitHub release listing, newest first like the API gives it
+    fn github_releases(tags: &[&str]) -> Reply {
+        let releases: Vec<serde_json::Value> = tags
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, v)| {
+                json!({
+                    "name": v,
+                    "tag_name": v,
+                    "prerelease": false,
+                    "draft": false,
+                    "published_at": format!("2024-01-0{}T00:00:00Z", i + 1),
+                    "html_url": format!("https://github.com/owner/repo/releases/tag/{}", v),
+                })
+            })
+            .collect();
+        Reply::json(&serde_json::Value::from(releases).to_string())
+    }
+
+    #[tokio::test]
+    async fn every_release_between_polls_is_alerted_in_order() {
+        let github = Stub::start();
+        let list = "/repos/owner/repo/releases";
+        github.route(list, github_releases(&["v1.0.0"]));
+        let (puller, _ctx) = puller(json!({
+            "name": "repo",
+            "url": format!("{}{}/latest", github.url(), list),
+        }));
+        let (tx, mut rx) = mpsc::channel(8);
+        puller.pull(tx.clone()).await.unwrap();
+        assert!(rx.try_recv().is_err());
+
+        github.route(list, github_releases(&["v1.0.0", "v1.1.0", "v1.2.0"]));
+        puller.pull(tx.clone()).await.unwrap();
+        let first = rx.try_recv().unwrap().release;
+        assert_eq!(first.detail.tag_name, "v1.1.0");
+        assert_eq!(first.previous_version.as_deref(), Some("1.0.0"));
+        let second = rx.try_recv().unwrap().release;
+        assert_eq!(second.detail.tag_name, "v1.2.0");
+        assert_eq!(second.previous_version.as_deref(), Some("1.1.0"));
+        assert!(rx.try_recv().is_err());
+
+        // nothing new was published since
+        puller.pull(tx.clone()).await.unwrap();
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn unchanged_listing_is_not_alerted_again() {
+        let github = Stub::start();
+        let list = "/repos/owner/repo/releases";
+        github.replies(
+            list,
+            vec![
+                github_releases(&["v1.0.0"]).header("ETag", "\"a\""),
+                Reply::status(304),
+            ],
+        );
+        let (puller, _ctx) = puller(json!({
+            "name": "repo",
+            "url": format!("{}{}/latest", github.url(), list),
+        }));
+        let (tx, mut rx) = mpsc::channel(8);
+        puller.pull(tx.clone()).await.unwrap();
+        puller.pull(tx.clone()).await.unwrap();
+        assert!(rx.try_recv().is_err());
+
+        let received = github.received();
+        assert_eq!(received[0].header("If-None-Match"), None);
+        assert_eq!(received[1].header("If-None-Match"), Some("\"a\""));
+    }
+
+    #[tokio::test]
+    async fn failed_pull_keeps_the_validators_of_the_last_one() {
+        let github = Stub::start();
+        let list = "/repos/owner/repo/releases";
+        github.replies(
+            list,
+            vec![
+                github_releases(&["v1.0.0"]).header("ETag", "\"a\""),
+                Reply::json("{").header("ETag", "\"b\""),
+                github_releases(&["v1.0.0", "v1.1.0"]).header("ETag", "\"c\""),
+            ],
+        );
+        let (puller, _ctx) = puller(json!({
+            "name": "repo",
+            "url": format!("{}{}/latest", github.url(), list),
+        }));
+        let (tx, mut rx) = mpsc::channel(8);
+        puller.pull(tx.clone()).await.unwrap();
+        assert!(puller.pull(tx.clone()).await.is_err());
+        puller.pull(tx.clone()).await.unwrap();
+        assert_eq!(rx.try_recv().unwrap().release.detail.tag_name, "v1.1.0");
+        assert!(rx.try_recv().is_err());
+
+        let received = github.received();
+        assert_eq!(received[1].header("If-None-Match"), Some("\"a\""));
+        // the response that failed to be processed is asked for again
+        assert_eq!(received[2].header("If-None-Match"), Some("\"a\""));
+    }
+
+    #[tokio::test]
+    async fn feed_entries_are_told_apart_by_their_id() {
+        let feed = Stub::start();
+        let atom = |entries: &[(&str, &str)]| {
+            let entries: String = entries
+                .iter()
+                .enumerate()
+                .map(|(i, (id, title))| {
+                    format!(
+                        "<entry><title>{}</title><id>{}</id><updated>2024-01-0{}T00:00:00Z</updated></entry>",
+                        title,
+                        id,
+                        i + 1
+                    )
+                })
+                .collect();
+            Reply::json(&format!(
+                r#"<feed xmlns="http://www.w3.org/2005/Atom"><title>Demo</title><id>urn:demo</id>{}</feed>"#,
+                entries
+            ))
+        };
+        feed.route("/feed", atom(&[("urn:1", "Weekly build")]));
+        let (puller, _ctx) = puller(json!({
+            "name": "demo",
+            "type": "feed",
+            "url": format!("{}/feed", feed.url()),
+        }));
+        let (tx, mut rx) = mpsc::channel(8);
+        puller.pull(tx.clone()).await.unwrap();
+        assert!(rx.try_recv().is_err());
+
+        // the same title again is a new entry, an edited one is not
+        feed.route(
+            "/feed",
+            atom(&[
+                ("urn:1", "Weekly build (edited)"),
+                ("urn:2", "Weekly build"),
+            ]),
+        );
+        puller.pull(tx.clone()).await.unwrap();
+        let event = rx.try_recv().unwrap().release;
+        assert_eq!(event.id(), "urn:2");
+        assert_eq!(event.version, None);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn new_digest_of_a_moving_tag_is_alerted() {
+        let registry = Stub::start();
+        let manifest = "/v2/team/app/manifests/1.25";
+        registry.route(
+            manifest,
+            Reply::json("").header("Docker-Content-Digest", "sha256:aaa"),
+        );
+        let (puller, _ctx) = puller(json!({
+            "name": "app",
+            "type": "oci",
+            "package": format!("{}/team/app", registry.host()),
+            "digestTag": "1.25",
+            // judged by the version of the tag
+            "semver": ">=1.20",
+        }));
+        let (tx, mut rx) = mpsc::channel(8);
+
+        // the first poll only records the digest
+        puller.pull(tx.clone()).await.unwrap();
+        assert!(rx.try_recv().is_err());
+        puller.pull(tx.clone()).await.unwrap();
+        assert!(rx.try_recv().is_err());
+
+        registry.route(
+            manifest,
+            Reply::json("").header("Docker-Content-Digest", "sha256:bbb"),
+        );
+        puller.pull(tx.clone()).await.unwrap();
+        let alert = rx.try_recv().unwrap();
+        assert_eq!(alert.source, SourceType::Oci);
+        let event = alert.release;
+        assert_eq!(event.detail.tag_name, "1.25@sha256:bbb");
+        assert_eq!(event.previous_version.as_deref(), Some("1.25@sha256:aaa"));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn post_release_is_alerted_after_its_release() {
+        let pypi = Stub::start();
+        let project = |versions: &[&str]| {
+            let releases: serde_json::Map<String, serde_json::Value> = versions
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    let time = format!("2024-01-0{}T00:00:00Z", i + 1);
+                    (v.to_string(), json!([{ "upload_time_iso_8601": time }]))
+                })
+                .collect();
+            Reply::json(&json!({ "info": { "name": "demo" }, "releases": releases }).to_string())
+        };
+        pypi.route("/pypi/demo/json", project(&["1.0"]));
+        let (puller, _ctx) = puller(json!({
+            "name": "demo",
+            "type": "pypi",
+            "url": pypi.url(),
+            "package": "demo",
+            "prerelease": "ignore",
+        }));
+        let (tx, mut rx) = mpsc::channel(8);
+        puller.pull(tx.clone()).await.unwrap();
+        assert!(rx.try_recv().is_err());
+
+        pypi.route("/pypi/demo/json", project(&["1.0", "1.0.post1"]));
+        puller.pull(tx.clone()).await.unwrap();
+        let event = rx.try_recv().unwrap().release;
+        assert_eq!(event.detail.tag_name, "1.0.post1");
+        assert_eq!(event.previous_version.as_deref(), Some("1.0.0"));
+
+        pypi.route(
+            "/pypi/demo/json",
+            project(&["1.0", "1.0.post1", "1.0.post2"]),
+        );
+        puller.pull(tx.clone()).await.unwrap();
+        assert_eq!(rx.try_recv().unwrap().release.detail.tag_name, "1.0.post2");
+        assert!(rx.try_recv().is_err());
+    }
+}